@@ -0,0 +1,231 @@
+//! Monte Carlo combat balancing: fight every enemy in `data.enemies.ron` many times with the same
+//! hero and report how those fights tend to go.
+//!
+//! Usage: `cargo run -- balance [--hero <file>] [--runs <n>] [--csv]`
+
+use rand::rngs::ThreadRng;
+use serde::Deserialize;
+
+use crate::config::config_sim::SimConfig;
+use crate::config::data_enemies::EnemiesData;
+use crate::config::data_items::ItemsData;
+use crate::game::combat::{resolve_combat_round, Combatant, Enemy};
+use crate::game::sim::event_handling::EnemyAbilityEvent;
+use crate::game::timed_effect::TemporaryModifier;
+use crate::game::ItemId;
+
+const DEFAULT_HERO_FILE: &str = "assets/config/balance.hero.ron";
+const DEFAULT_RUNS: u32 = 1000;
+/// Fights that haven't been decided after this many rounds are counted as a loss.
+const MAX_ROUNDS: u32 = 500;
+
+/// The hero that is sent into every fight.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeroBlueprint {
+    combat_stats: Combatant,
+    /// The stat bonuses of these items are added to the hero before the fights start.
+    #[serde(default)]
+    equipped: Vec<ItemId>,
+}
+
+struct Options {
+    hero_file: String,
+    runs: u32,
+    csv: bool,
+}
+
+/// The summed results of all fights against one enemy.
+#[derive(Default)]
+struct Tally {
+    wins: u32,
+    rounds: u64,
+    hp_lost: i64,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let sim: SimConfig = super::load_config("config.sim.ron")?;
+    let enemies: EnemiesData = super::load_config("data.enemies.ron")?;
+    let items: ItemsData = super::load_config("data.items.ron")?;
+    let blueprint: HeroBlueprint = super::load_ron(&options.hero_file)?;
+    let hero = build_hero(&blueprint, &items)?;
+
+    let mut rng = rand::thread_rng();
+    let tallies: Vec<(&Enemy, Tally)> = enemies
+        .enemies
+        .iter()
+        .map(|enemy| {
+            let mut tally = Tally::default();
+            for _ in 0..options.runs {
+                let (won, rounds, hero_after) =
+                    simulate_fight(hero, enemy, &enemies, sim.duration_millis, &mut rng);
+                if won {
+                    tally.wins += 1;
+                }
+                tally.rounds += rounds as u64;
+                tally.hp_lost += (hero.health - hero_after.health.max(0)) as i64;
+            }
+            (enemy, tally)
+        })
+        .collect();
+
+    println!("Hero: {} (health/max/proficiency/res/damage)", hero);
+    if options.csv {
+        println!("enemy,runs,win_rate,avg_rounds,avg_hp_lost");
+    } else {
+        println!(
+            "{:<20} {:>6} {:>9} {:>11} {:>12}",
+            "enemy", "runs", "win rate", "avg rounds", "avg HP lost"
+        );
+    }
+    let runs = options.runs.max(1) as f64;
+    for (enemy, tally) in tallies {
+        let win_rate = tally.wins as f64 / runs * 100.0;
+        let avg_rounds = tally.rounds as f64 / runs;
+        let avg_hp_lost = tally.hp_lost as f64 / runs;
+        if options.csv {
+            println!(
+                "{},{},{:.2},{:.2},{:.2}",
+                enemy.name, options.runs, win_rate, avg_rounds, avg_hp_lost
+            );
+        } else {
+            println!(
+                "{:<20} {:>6} {:>8.1}% {:>11.1} {:>12.1}",
+                enemy.name, options.runs, win_rate, avg_rounds, avg_hp_lost
+            );
+        }
+    }
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        hero_file: DEFAULT_HERO_FILE.to_string(),
+        runs: DEFAULT_RUNS,
+        csv: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hero" => {
+                options.hero_file = args.next().ok_or("--hero needs a file path")?.clone();
+            }
+            "--runs" => {
+                options.runs = args
+                    .next()
+                    .and_then(|runs| runs.parse().ok())
+                    .filter(|runs| *runs > 0)
+                    .ok_or("--runs needs a positive number")?;
+            }
+            "--csv" => options.csv = true,
+            _ => {
+                return Err(format!(
+                    "Unknown argument: {}\nUsage: balance [--hero <file>] [--runs <n>] [--csv]",
+                    arg
+                ))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Apply the stat bonuses of the equipped items and start the hero off at full health.
+fn build_hero(blueprint: &HeroBlueprint, items: &ItemsData) -> Result<Combatant, String> {
+    let mut hero = blueprint.combat_stats;
+    for item_id in blueprint.equipped.iter() {
+        let (_, item) = items
+            .try_get_item(item_id.clone())
+            .ok_or_else(|| format!("Equipped item {:?} is not in data.items.ron", item_id))?;
+        if let Some(bonus) = item.stat_bonuses {
+            hero.max_health += bonus.max_health;
+            hero.proficiency += bonus.proficiency;
+            hero.damage_res += bonus.damage_res;
+            hero.damage_bonus += bonus.damage_bonus;
+        }
+    }
+    hero.health = hero.max_health;
+    Ok(hero)
+}
+
+/// Fight one enemy (and whatever it summons) to the end. Returns whether the hero won, how many
+/// rounds it took, and the state the hero was left in.
+fn simulate_fight(
+    mut hero: Combatant,
+    enemy: &Enemy,
+    enemies: &EnemiesData,
+    round_millis: u64,
+    rng: &mut ThreadRng,
+) -> (bool, u32, Combatant) {
+    let mut enemy = enemy.clone();
+    let mut reinforcements: Vec<Enemy> = vec![];
+    // Active modifiers, with the number of rounds they have left.
+    let mut modifiers: Vec<(TemporaryModifier, u32)> = vec![];
+
+    for round in 1..=MAX_ROUNDS {
+        let result = resolve_combat_round(&mut enemy, &mut hero, rng);
+        for event in result.ability_events {
+            match event {
+                EnemyAbilityEvent::Summon(enemy_id) => {
+                    if let Some(summoned) = enemies.enemies.iter().find(|e| e.enemy_id == enemy_id)
+                    {
+                        reinforcements.push(summoned.clone());
+                    }
+                }
+                EnemyAbilityEvent::ApplyModifier(modifier) => {
+                    let target = if modifier.on_hero {
+                        &mut hero
+                    } else {
+                        &mut enemy.combat_stats
+                    };
+                    apply_modifier(target, &modifier, 1);
+                    let rounds =
+                        ((modifier.time * 1000.0) / round_millis.max(1) as f32).ceil() as u32;
+                    modifiers.push((modifier, rounds.max(1)));
+                }
+                // The backpack doesn't take part in the simulation.
+                EnemyAbilityEvent::StealItem => {}
+            }
+        }
+
+        for (modifier, rounds_left) in modifiers.iter_mut() {
+            *rounds_left -= 1;
+            if *rounds_left == 0 {
+                let target = if modifier.on_hero {
+                    &mut hero
+                } else {
+                    &mut enemy.combat_stats
+                };
+                apply_modifier(target, modifier, -1);
+            }
+        }
+        modifiers.retain(|(_, rounds_left)| *rounds_left > 0);
+
+        if hero.health < 1 {
+            return (false, round, hero);
+        }
+        if enemy.combat_stats.health < 1 {
+            match reinforcements.pop() {
+                Some(next) => {
+                    // Modifiers on the enemy die with it, same as in the game.
+                    modifiers.retain(|(modifier, _)| modifier.on_hero);
+                    enemy = next;
+                }
+                None => return (true, round, hero),
+            }
+        }
+    }
+    (false, MAX_ROUNDS, hero)
+}
+
+/// Add (`sign` = 1) or remove (`sign` = -1) the effects of a modifier, the way
+/// `tick_temporary_modifiers` does in the game.
+fn apply_modifier(target: &mut Combatant, modifier: &TemporaryModifier, sign: i32) {
+    target.damage_bonus += sign * modifier.damage_mod;
+    target.max_health += sign * modifier.max_health_mod;
+    target.damage_res += sign * modifier.damage_res_mod;
+    target.proficiency += sign * modifier.combat_prof_mod;
+    if target.health > target.max_health {
+        target.health = target.max_health;
+    }
+}
@@ -0,0 +1,557 @@
+//! Export how items lead to one another, for design reviews: which items unlock or grow others in
+//! the evolution chain, which are ingredients of which recipes, and which win the game. Writes a
+//! Graphviz DOT file and a JSON adjacency list, and flags the items that can't be reached from the
+//! starting backpack and the loot, take part in a cycle, or are never used as an ingredient.
+//!
+//! Usage: `cargo run -- graph [--dot <file>] [--json <file>]`
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::config::data_blueprint::BlueprintData;
+use crate::config::data_enemies::EnemiesData;
+use crate::config::data_items::ItemsData;
+use crate::config::data_recipes::RecipesData;
+use crate::game::combat::DropTable;
+use crate::game::{ItemId, ItemTag, EVOLUTION_RULES, POPULATION_SOURCES, STARTING_ITEMS};
+
+const DEFAULT_DOT_FILE: &str = "item_graph.dot";
+const DEFAULT_JSON_FILE: &str = "item_graph.json";
+/// The node that every item which wins the game points to.
+const WIN_NODE: &str = "Win";
+
+struct Options {
+    dot_file: String,
+    json_file: String,
+}
+
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum EdgeKind {
+    /// At least `count` of the item are needed to unlock the other one.
+    Unlocks,
+    /// The other item grows with the number of this item on every evolution.
+    Grows,
+    /// The item counts towards the population that the other item needs.
+    Population,
+    /// `count` of the item are used up in the recipe for the other one.
+    Ingredient,
+    /// Having the item wins the game.
+    Wins,
+}
+
+#[derive(Serialize)]
+struct Edge {
+    to: String,
+    kind: EdgeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    id: String,
+    name: String,
+    edges: Vec<Edge>,
+    /// The item isn't in the starting backpack or the loot, and nothing in the evolution chain or
+    /// the recipes can make it from those.
+    unreachable: bool,
+    /// The item takes part in a cycle of unlocks and ingredients.
+    in_cycle: bool,
+    never_an_ingredient: bool,
+}
+
+#[derive(Serialize)]
+struct Graph {
+    nodes: Vec<Node>,
+    /// The groups of items that unlock or are made from each other in a circle.
+    cycles: Vec<Vec<String>>,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let items: ItemsData = super::load_config("data.items.ron")?;
+    let recipes: RecipesData = super::load_config("data.recipes.ron")?;
+    let blueprint: BlueprintData = super::load_config("data.blueprint.ron")?;
+    let enemies: EnemiesData = super::load_config("data.enemies.ron")?;
+    let sources = item_sources(&blueprint, &enemies);
+    let graph = build_graph(&items, &recipes, &sources);
+
+    std::fs::write(&options.dot_file, to_dot(&graph))
+        .map_err(|e| format!("Could not write {}: {}", options.dot_file, e))?;
+    let json = serde_json::to_string_pretty(&graph).map_err(|e| e.to_string())?;
+    std::fs::write(&options.json_file, json)
+        .map_err(|e| format!("Could not write {}: {}", options.json_file, e))?;
+
+    println!(
+        "Wrote {} items to {} and {}.",
+        graph.nodes.len(),
+        options.dot_file,
+        options.json_file
+    );
+    let flagged = |flag: fn(&Node) -> bool| {
+        graph
+            .nodes
+            .iter()
+            .filter(|node| flag(node))
+            .map(|node| node.id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!("Unreachable: {}", flagged(|node| node.unreachable));
+    for cycle in graph.cycles.iter() {
+        println!("Cycle: {}", cycle.join(" -> "));
+    }
+    println!(
+        "Never an ingredient: {}",
+        flagged(|node| node.never_an_ingredient)
+    );
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        dot_file: DEFAULT_DOT_FILE.to_string(),
+        json_file: DEFAULT_JSON_FILE.to_string(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => {
+                options.dot_file = args.next().ok_or("--dot needs a file path")?.clone();
+            }
+            "--json" => {
+                options.json_file = args.next().ok_or("--json needs a file path")?.clone();
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown argument: {}\nUsage: graph [--dot <file>] [--json <file>]",
+                    arg
+                ))
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn node_id(id: &ItemId) -> String {
+    format!("{:?}", id)
+}
+
+/// The items that a run can get without making them: the starting backpack, the loot of the
+/// dungeon's rooms, and what enemies drop. Items that a drop table never picks don't count.
+fn item_sources(blueprint: &BlueprintData, enemies: &EnemiesData) -> HashSet<String> {
+    let droppable = |table: &DropTable| {
+        table
+            .items
+            .iter()
+            .zip(table.chances.iter())
+            .filter(|(_, chance)| **chance > 0)
+            .map(|(id, _)| node_id(id))
+            .collect::<Vec<_>>()
+    };
+    let room_loot = blueprint.levels.iter().flat_map(|level| {
+        std::iter::once(&level.default_loot).chain(
+            level
+                .segments
+                .iter()
+                .filter_map(|segment| segment.custom_loot.as_ref()),
+        )
+    });
+    let enemy_loot = enemies.enemies.iter().map(|enemy| &enemy.drop_table);
+    room_loot
+        .chain(enemy_loot)
+        .flat_map(droppable)
+        .chain(STARTING_ITEMS.iter().map(|(id, _, _)| node_id(id)))
+        .collect()
+}
+
+fn build_graph(items: &ItemsData, recipes: &RecipesData, sources: &HashSet<String>) -> Graph {
+    // Every item in the data, then whatever the evolution chain and the recipes mention on top.
+    let mut names = items
+        .items
+        .iter()
+        .map(|(_, item)| (node_id(&item.id), item.name.clone()))
+        .collect::<Vec<_>>();
+    let mentioned = EVOLUTION_RULES
+        .iter()
+        .flat_map(|rule| {
+            std::iter::once(rule.item.clone())
+                .chain(rule.unlocked_by.iter().map(|(id, _)| id.clone()))
+                .chain(rule.growth_sources(items))
+        })
+        .chain(recipes.recipes.iter().flat_map(|recipe| {
+            std::iter::once(recipe.result.clone())
+                .chain(recipe.ingredients.iter().map(|i| i.item_id.clone()))
+        }));
+    for id in mentioned {
+        let id = node_id(&id);
+        if !names.iter().any(|(known, _)| *known == id) {
+            names.push((id.clone(), id));
+        }
+    }
+    names.push((WIN_NODE.to_string(), WIN_NODE.to_string()));
+
+    let mut edges = HashMap::<String, Vec<Edge>>::new();
+    let mut add_edge = |from: &ItemId, to: String, kind, count| {
+        edges
+            .entry(node_id(from))
+            .or_default()
+            .push(Edge { to, kind, count });
+    };
+    for rule in EVOLUTION_RULES {
+        for (id, count) in rule.unlocked_by {
+            add_edge(id, node_id(&rule.item), EdgeKind::Unlocks, Some(*count));
+        }
+        for id in rule.growth_sources(items) {
+            add_edge(&id, node_id(&rule.item), EdgeKind::Grows, None);
+        }
+        if rule.population > 0 {
+            for (id, _) in POPULATION_SOURCES {
+                add_edge(id, node_id(&rule.item), EdgeKind::Population, None);
+            }
+        }
+    }
+    for recipe in recipes.recipes.iter() {
+        for ingr in recipe.ingredients.iter() {
+            let count = Some(ingr.quantity.max(0) as usize);
+            add_edge(
+                &ingr.item_id,
+                node_id(&recipe.result),
+                EdgeKind::Ingredient,
+                count,
+            );
+        }
+    }
+    for (_, item) in items.items.iter() {
+        if item.has_tag(ItemTag::Victory) {
+            add_edge(&item.id, WIN_NODE.to_string(), EdgeKind::Wins, None);
+        }
+    }
+
+    let reachable = reachable_items(items, recipes, sources);
+    let cycles = find_cycles(&names, &edges);
+    let ingredients = recipes
+        .recipes
+        .iter()
+        .flat_map(|recipe| recipe.ingredients.iter().map(|i| node_id(&i.item_id)))
+        .collect::<HashSet<_>>();
+    let nodes = names
+        .into_iter()
+        .map(|(id, name)| Node {
+            unreachable: !reachable.contains(&id),
+            in_cycle: cycles.iter().any(|cycle| cycle.contains(&id)),
+            never_an_ingredient: id != WIN_NODE && !ingredients.contains(&id),
+            edges: edges.remove(&id).unwrap_or_default(),
+            id,
+            name,
+        })
+        .collect();
+    Graph { nodes, cycles }
+}
+
+/// Graphviz can lay this out with `dot -Tsvg item_graph.dot -o item_graph.svg`.
+fn to_dot(graph: &Graph) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "\\\""));
+    let mut dot = String::from("digraph items {\n    rankdir=LR;\n    node [shape=box];\n");
+    for node in graph.nodes.iter() {
+        let mut label = if node.name == node.id {
+            node.id.clone()
+        } else {
+            format!("{}\n{}", node.name, node.id)
+        };
+        let mut attrs = vec![];
+        if node.id == WIN_NODE {
+            attrs.push("shape=doublecircle".to_string());
+        }
+        if node.unreachable {
+            label.push_str("\n[unreachable]");
+            attrs.push("color=red, fontcolor=red".to_string());
+        }
+        if node.in_cycle {
+            label.push_str("\n[cycle]");
+            attrs.push("style=filled, fillcolor=orange".to_string());
+        }
+        if node.never_an_ingredient {
+            attrs.push("peripheries=2".to_string());
+        }
+        attrs.insert(0, format!("label={}", quote(&label)));
+        dot.push_str(&format!(
+            "    {} [{}];\n",
+            quote(&node.id),
+            attrs.join(", ")
+        ));
+    }
+    for node in graph.nodes.iter() {
+        for edge in node.edges.iter() {
+            let style = match edge.kind {
+                EdgeKind::Unlocks => "color=black",
+                EdgeKind::Grows => "style=dashed, color=goldenrod",
+                EdgeKind::Population => "style=dotted, color=gray",
+                EdgeKind::Ingredient => "color=blue",
+                EdgeKind::Wins => "color=darkgreen, penwidth=2",
+            };
+            let label = edge
+                .count
+                .map(|count| format!(", label=\"x{}\"", count))
+                .unwrap_or_default();
+            dot.push_str(&format!(
+                "    {} -> {} [{}{}];\n",
+                quote(&node.id),
+                quote(&edge.to),
+                style,
+                label
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// One way to make an item: an evolution rule or a recipe.
+struct Maker {
+    result: String,
+    /// All of these are needed.
+    needs: Vec<String>,
+    /// Some food to make up the population is needed as well.
+    needs_population: bool,
+}
+
+/// The items that can turn up in a run. The given sources are there from the start, and an item
+/// that the evolution chain or a recipe makes needs all of the items that go into it.
+fn reachable_items(
+    items: &ItemsData,
+    recipes: &RecipesData,
+    sources: &HashSet<String>,
+) -> HashSet<String> {
+    let rule_makers = EVOLUTION_RULES
+        .iter()
+        .filter(|rule| rule.unlockable)
+        .map(|rule| Maker {
+            result: node_id(&rule.item),
+            needs: rule.unlocked_by.iter().map(|(id, _)| node_id(id)).collect(),
+            needs_population: rule.population > 0,
+        });
+    let recipe_makers = recipes.recipes.iter().map(|recipe| Maker {
+        result: node_id(&recipe.result),
+        needs: recipe
+            .ingredients
+            .iter()
+            .map(|ingr| node_id(&ingr.item_id))
+            .collect(),
+        needs_population: false,
+    });
+    let makers = rule_makers.chain(recipe_makers).collect::<Vec<_>>();
+    let winners = items
+        .items
+        .iter()
+        .filter(|(_, item)| item.has_tag(ItemTag::Victory))
+        .map(|(_, item)| node_id(&item.id))
+        .collect::<Vec<_>>();
+
+    let mut reachable = sources.clone();
+    loop {
+        let before = reachable.len();
+        let has_population = POPULATION_SOURCES
+            .iter()
+            .any(|(id, _)| reachable.contains(&node_id(id)));
+        for maker in makers.iter() {
+            if maker.needs.iter().all(|id| reachable.contains(id))
+                && (!maker.needs_population || has_population)
+            {
+                reachable.insert(maker.result.clone());
+            }
+        }
+        if winners.iter().any(|id| reachable.contains(id)) {
+            reachable.insert(WIN_NODE.to_string());
+        }
+        if reachable.len() == before {
+            return reachable;
+        }
+    }
+}
+
+/// The groups of items that depend on each other in a circle through unlocks and ingredients, found
+/// with Tarjan's algorithm. Growth is left out, feeding back into itself is what it is for.
+fn find_cycles(names: &[(String, String)], edges: &HashMap<String, Vec<Edge>>) -> Vec<Vec<String>> {
+    struct Search<'a> {
+        edges: &'a HashMap<String, Vec<Edge>>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        cycles: Vec<Vec<String>>,
+    }
+
+    impl<'a> Search<'a> {
+        fn visit(&mut self, id: &'a str) {
+            let index = self.index.len();
+            self.index.insert(id, index);
+            self.low.insert(id, index);
+            self.stack.push(id);
+            let edges = self.edges;
+            let next = edges
+                .get(id)
+                .into_iter()
+                .flatten()
+                .filter(|edge| matches!(edge.kind, EdgeKind::Unlocks | EdgeKind::Ingredient));
+            for edge in next {
+                let to = edge.to.as_str();
+                if !self.index.contains_key(to) {
+                    self.visit(to);
+                    self.low.insert(id, self.low[id].min(self.low[to]));
+                } else if self.stack.contains(&to) {
+                    self.low.insert(id, self.low[id].min(self.index[to]));
+                }
+            }
+            if self.low[id] != self.index[id] {
+                return;
+            }
+            let mut component = vec![];
+            while let Some(member) = self.stack.pop() {
+                component.push(member.to_string());
+                if member == id {
+                    break;
+                }
+            }
+            let needs_itself = self.edges.get(id).into_iter().flatten().any(|edge| {
+                edge.to == id && matches!(edge.kind, EdgeKind::Unlocks | EdgeKind::Ingredient)
+            });
+            if component.len() > 1 || needs_itself {
+                component.reverse();
+                self.cycles.push(component);
+            }
+        }
+    }
+
+    let mut search = Search {
+        edges,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: vec![],
+        cycles: vec![],
+    };
+    for (id, _) in names {
+        if !search.index.contains_key(id.as_str()) {
+            search.visit(id);
+        }
+    }
+    search.cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::combat::Enemy;
+    use crate::game::dungeon_gen::{LevelBlueprint, SegmentBlueprint};
+    use crate::game::{Ingredient, Item, Recipe};
+    use crate::positioning::Dimens;
+
+    fn recipes(recipes: &[(ItemId, &[ItemId])]) -> RecipesData {
+        RecipesData {
+            recipes: recipes
+                .iter()
+                .map(|(result, ingredients)| Recipe {
+                    result: result.clone(),
+                    ingredients: ingredients
+                        .iter()
+                        .map(|id| Ingredient {
+                            item_id: id.clone(),
+                            quantity: 1,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn ids(ids: &[ItemId]) -> HashSet<String> {
+        ids.iter().map(node_id).collect()
+    }
+
+    fn drop_table(items: &[(ItemId, u32)]) -> DropTable {
+        DropTable {
+            items: items.iter().map(|(id, _)| id.clone()).collect(),
+            chances: items.iter().map(|(_, chance)| *chance).collect(),
+        }
+    }
+
+    #[test]
+    fn only_sources_and_what_they_make_are_reachable() {
+        let recipes = recipes(&[
+            (ItemId::Vial, &[ItemId::HerbGreen, ItemId::HerbRed]),
+            (ItemId::SwordRusty, &[ItemId::ScrollBasic1]),
+        ]);
+        let sources = ids(&[ItemId::HerbGreen, ItemId::HerbRed]);
+        let reachable = reachable_items(&ItemsData::default(), &recipes, &sources);
+        assert!(reachable.is_superset(&ids(&[ItemId::HerbGreen, ItemId::HerbRed, ItemId::Vial])));
+        assert!(!reachable.contains(&node_id(&ItemId::ScrollBasic1)));
+        assert!(!reachable.contains(&node_id(&ItemId::SwordRusty)));
+    }
+
+    #[test]
+    fn evolution_needs_its_unlocks() {
+        let no_recipes = recipes(&[]);
+        let reachable = reachable_items(
+            &ItemsData::default(),
+            &no_recipes,
+            &ids(&[ItemId::StoneTool]),
+        );
+        assert!(reachable.is_superset(&ids(&[ItemId::Wheat, ItemId::BronzeTool])));
+        assert!(!reachable.contains(&node_id(&ItemId::Meat)));
+        let reachable = reachable_items(
+            &ItemsData::default(),
+            &no_recipes,
+            &ids(&[ItemId::StoneTool, ItemId::GatheringAndHunting]),
+        );
+        assert!(reachable.contains(&node_id(&ItemId::Meat)));
+    }
+
+    #[test]
+    fn reaching_a_victory_item_wins() {
+        let items = ItemsData {
+            items: vec![(
+                Dimens::unit(),
+                Item {
+                    id: ItemId::Vial,
+                    tags: vec![ItemTag::Victory],
+                    ..Default::default()
+                },
+            )],
+        };
+        let recipes = recipes(&[(ItemId::Vial, &[ItemId::HerbGreen])]);
+        let won = |sources: &[ItemId]| {
+            reachable_items(&items, &recipes, &ids(sources)).contains(WIN_NODE)
+        };
+        assert!(won(&[ItemId::HerbGreen]));
+        assert!(!won(&[ItemId::HerbRed]));
+    }
+
+    #[test]
+    fn sources_are_the_starting_items_and_the_loot() {
+        let blueprint = BlueprintData {
+            levels: vec![LevelBlueprint {
+                depth: 0,
+                default_loot: drop_table(&[(ItemId::HerbGreen, 20)]),
+                segments: vec![SegmentBlueprint {
+                    types: HashMap::new(),
+                    enemies: None,
+                    custom_loot: Some(drop_table(&[(ItemId::ScrollBasic1, 100)])),
+                    custom_flavour: None,
+                }],
+            }],
+        };
+        let enemies = EnemiesData {
+            enemies: vec![Enemy {
+                drop_table: drop_table(&[(ItemId::SwordRusty, 50), (ItemId::HerbRed, 0)]),
+                ..Default::default()
+            }],
+        };
+        let starting = STARTING_ITEMS.iter().map(|(id, _, _)| id.clone());
+        let expected = [ItemId::HerbGreen, ItemId::ScrollBasic1, ItemId::SwordRusty]
+            .into_iter()
+            .chain(starting)
+            .collect::<Vec<_>>();
+        assert_eq!(item_sources(&blueprint, &enemies), ids(&expected));
+    }
+}
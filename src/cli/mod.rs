@@ -0,0 +1,39 @@
+//! Command line tools that run without opening the game window. They are started by passing the
+//! name of the tool as the first argument, for example `cargo run -- balance --runs 5000`.
+
+mod balance;
+mod graph;
+
+/// Run the tool named in the first argument, if there is one. Returns the exit code of the tool,
+/// or `None` if the arguments aren't meant for a tool and the game should start as usual.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "balance" => balance::run(rest),
+        "graph" => graph::run(rest),
+        _ => return None,
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    })
+}
+
+/// Read a ron file from the override directory if it's there, or from the default directory.
+fn load_config<T: serde::de::DeserializeOwned>(file_name: &str) -> Result<T, String> {
+    let override_path = format!("assets/config/override/{}", file_name);
+    let path = if std::path::Path::new(&override_path).exists() {
+        override_path
+    } else {
+        format!("assets/config/default/{}", file_name)
+    };
+    load_ron(&path)
+}
+
+fn load_ron<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    ron::de::from_bytes(&bytes).map_err(|e| format!("Could not parse {}: {}", path, e))
+}
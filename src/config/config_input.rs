@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::{GamepadButton, GamepadButtonType, Input, KeyCode, MouseButton};
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, TypeUuid)]
+#[serde(deny_unknown_fields)]
+#[uuid = "5b0f5a3e-2d7c-4c8e-9b61-0f3e7a1d4c92"]
+pub struct InputConfig {
+    /// If true, clicking an item picks it up and the next click puts it down again, so the mouse
+    /// button doesn't have to be held down during a drag. The CancelDrag binding puts the item back.
+    pub click_to_drag: bool,
+    /// The ways to trigger each action. Each binding is a list of buttons that all have to be
+    /// held down together. Actions that are left out keep their default bindings.
+    #[serde(default)]
+    pub bindings: HashMap<InputAction, Vec<Vec<InputButton>>>,
+}
+
+impl InputConfig {
+    /// The bindings of the action, or its default bindings if the config doesn't mention it.
+    pub fn bindings_for(&self, action: InputAction) -> Vec<Vec<InputButton>> {
+        self.bindings
+            .get(&action)
+            .cloned()
+            .unwrap_or_else(|| action.default_bindings())
+    }
+
+    /// Whether all buttons of any of the action's bindings are held down.
+    pub fn pressed(
+        &self,
+        action: InputAction,
+        keys: &Input<KeyCode>,
+        buttons: &Input<MouseButton>,
+        pads: &Input<GamepadButton>,
+    ) -> bool {
+        self.bindings_for(action).iter().any(|binding| {
+            !binding.is_empty()
+                && binding
+                    .iter()
+                    .all(|button| button.pressed(keys, buttons, pads))
+        })
+    }
+
+    /// Whether one of the action's bindings was completed this frame: all of its buttons are held
+    /// down, and at least one of them just went down.
+    pub fn just_pressed(
+        &self,
+        action: InputAction,
+        keys: &Input<KeyCode>,
+        buttons: &Input<MouseButton>,
+        pads: &Input<GamepadButton>,
+    ) -> bool {
+        self.just_pressed_binding(action, keys, buttons, pads)
+            .is_some()
+    }
+
+    /// Same as `just_pressed`, but also takes the button presses so that later systems don't see
+    /// them.
+    pub fn clear_just_pressed(
+        &self,
+        action: InputAction,
+        keys: &mut Input<KeyCode>,
+        buttons: &mut Input<MouseButton>,
+        pads: &mut Input<GamepadButton>,
+    ) -> bool {
+        let Some(binding) = self.just_pressed_binding(action, keys, buttons, pads) else {
+            return false;
+        };
+        for button in binding {
+            button.clear_just_pressed(keys, buttons, pads);
+        }
+        true
+    }
+
+    fn just_pressed_binding(
+        &self,
+        action: InputAction,
+        keys: &Input<KeyCode>,
+        buttons: &Input<MouseButton>,
+        pads: &Input<GamepadButton>,
+    ) -> Option<Vec<InputButton>> {
+        self.bindings_for(action).into_iter().find(|binding| {
+            !binding.is_empty()
+                && binding
+                    .iter()
+                    .all(|button| button.pressed(keys, buttons, pads))
+                && binding
+                    .iter()
+                    .any(|button| button.just_pressed(keys, buttons, pads))
+        })
+    }
+}
+
+/// Everything the player can rebind.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    /// Held while clicking an item to use, equip or consume it.
+    UseItem,
+    /// Held while dragging a stack to split off half of it.
+    SplitStack,
+    /// Held while clicking an item to delete it.
+    DeleteItem,
+    /// Turns the item that is being dragged a quarter clockwise.
+    RotateItem,
+    /// Puts the item that is being dragged back where it was.
+    CancelDrag,
+    /// Opens the context menu of the item under the mouse.
+    OpenContextMenu,
+    /// Moves the grid cursor one cell up.
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    /// Clicks where the grid cursor is, to pick up and drop items or press buttons.
+    CursorClick,
+    /// Uses the item under the grid cursor.
+    CursorUse,
+    /// Deletes the item under the grid cursor.
+    CursorDelete,
+    /// Moves the grid cursor to the combine button and presses it.
+    CursorJump,
+    /// Lets the hero walk on after a time jump.
+    ContinueDungeon,
+    /// Switches to the backpack of the other era while the hero waits.
+    SwitchBackpack,
+    /// Steps the backpack filter through the item tags.
+    CycleInventoryFilter,
+    OpenRecipeBook,
+    OpenTechTree,
+    /// Opens the backpacks of both eras side by side.
+    OpenEraView,
+    OpenCombatLog,
+    /// Whether every combat round is written to the feed.
+    ToggleVerboseFeed,
+    ToggleFullscreen,
+    /// Backs out of the game to the main menu.
+    Back,
+    /// Opens the window to rebind these actions.
+    OpenControls,
+    /// For testing: skips the opening in debug builds.
+    SkipOpening,
+    /// For testing: gives the hero a damage bonus for a while.
+    TestBuffHero,
+    /// For testing: gives the enemy a proficiency malus for a while.
+    TestDebuffEnemy,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 28] = [
+        InputAction::UseItem,
+        InputAction::SplitStack,
+        InputAction::DeleteItem,
+        InputAction::RotateItem,
+        InputAction::CancelDrag,
+        InputAction::OpenContextMenu,
+        InputAction::CursorUp,
+        InputAction::CursorDown,
+        InputAction::CursorLeft,
+        InputAction::CursorRight,
+        InputAction::CursorClick,
+        InputAction::CursorUse,
+        InputAction::CursorDelete,
+        InputAction::CursorJump,
+        InputAction::ContinueDungeon,
+        InputAction::SwitchBackpack,
+        InputAction::CycleInventoryFilter,
+        InputAction::OpenRecipeBook,
+        InputAction::OpenTechTree,
+        InputAction::OpenEraView,
+        InputAction::OpenCombatLog,
+        InputAction::ToggleVerboseFeed,
+        InputAction::ToggleFullscreen,
+        InputAction::Back,
+        InputAction::OpenControls,
+        InputAction::SkipOpening,
+        InputAction::TestBuffHero,
+        InputAction::TestDebuffEnemy,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputAction::UseItem => "Use item (hold + click)",
+            InputAction::SplitStack => "Split stack (hold + drag)",
+            InputAction::DeleteItem => "Delete item (hold + click)",
+            InputAction::RotateItem => "Rotate item while dragging",
+            InputAction::CancelDrag => "Cancel drag",
+            InputAction::OpenContextMenu => "Item menu (on an item)",
+            InputAction::CursorUp => "Cursor up",
+            InputAction::CursorDown => "Cursor down",
+            InputAction::CursorLeft => "Cursor left",
+            InputAction::CursorRight => "Cursor right",
+            InputAction::CursorClick => "Cursor: pick up / drop",
+            InputAction::CursorUse => "Cursor: use item",
+            InputAction::CursorDelete => "Cursor: delete item",
+            InputAction::CursorJump => "Cursor: jump through time",
+            InputAction::ContinueDungeon => "Continue the dungeon",
+            InputAction::SwitchBackpack => "Switch backpack",
+            InputAction::CycleInventoryFilter => "Cycle backpack filter",
+            InputAction::OpenRecipeBook => "Open recipe book",
+            InputAction::OpenTechTree => "Open tech tree",
+            InputAction::OpenEraView => "Open era view",
+            InputAction::OpenCombatLog => "Open combat log",
+            InputAction::ToggleVerboseFeed => "Toggle verbose combat feed",
+            InputAction::ToggleFullscreen => "Toggle fullscreen",
+            InputAction::Back => "Back to the main menu",
+            InputAction::OpenControls => "Open controls",
+            InputAction::SkipOpening => "Test: skip opening",
+            InputAction::TestBuffHero => "Test: buff hero",
+            InputAction::TestDebuffEnemy => "Test: debuff enemy",
+        }
+    }
+
+    pub fn default_bindings(&self) -> Vec<Vec<InputButton>> {
+        use GamepadButtonType::*;
+        use InputButton::{Key, Mouse, Pad};
+        match self {
+            InputAction::UseItem => vec![vec![Key(KeyCode::LShift)], vec![Key(KeyCode::RShift)]],
+            InputAction::SplitStack => {
+                vec![vec![Key(KeyCode::LControl)], vec![Key(KeyCode::RControl)]]
+            }
+            InputAction::DeleteItem => vec![
+                vec![Key(KeyCode::LControl), Key(KeyCode::LAlt)],
+                vec![Key(KeyCode::RControl), Key(KeyCode::RAlt)],
+            ],
+            InputAction::RotateItem => vec![
+                vec![Key(KeyCode::R)],
+                vec![Mouse(MouseButton::Right)],
+                vec![Pad(RightTrigger)],
+            ],
+            InputAction::CancelDrag => vec![vec![Key(KeyCode::Escape)], vec![Pad(East)]],
+            InputAction::OpenContextMenu => vec![vec![Mouse(MouseButton::Right)]],
+            InputAction::CursorUp => {
+                vec![
+                    vec![Key(KeyCode::Up)],
+                    vec![Key(KeyCode::W)],
+                    vec![Pad(DPadUp)],
+                ]
+            }
+            InputAction::CursorDown => {
+                vec![
+                    vec![Key(KeyCode::Down)],
+                    vec![Key(KeyCode::S)],
+                    vec![Pad(DPadDown)],
+                ]
+            }
+            InputAction::CursorLeft => {
+                vec![
+                    vec![Key(KeyCode::Left)],
+                    vec![Key(KeyCode::A)],
+                    vec![Pad(DPadLeft)],
+                ]
+            }
+            InputAction::CursorRight => {
+                vec![
+                    vec![Key(KeyCode::Right)],
+                    vec![Key(KeyCode::D)],
+                    vec![Pad(DPadRight)],
+                ]
+            }
+            InputAction::CursorClick => vec![vec![Key(KeyCode::Return)], vec![Pad(South)]],
+            InputAction::CursorUse => vec![vec![Key(KeyCode::E)], vec![Pad(West)]],
+            InputAction::CursorDelete => vec![vec![Key(KeyCode::Delete)], vec![Pad(Select)]],
+            InputAction::CursorJump => vec![vec![Key(KeyCode::Tab)], vec![Pad(North)]],
+            InputAction::ContinueDungeon => vec![vec![Key(KeyCode::Space)]],
+            InputAction::SwitchBackpack => vec![vec![Key(KeyCode::B)]],
+            InputAction::CycleInventoryFilter => vec![vec![Key(KeyCode::T)]],
+            InputAction::OpenRecipeBook => vec![vec![Key(KeyCode::J)]],
+            InputAction::OpenTechTree => vec![vec![Key(KeyCode::U)]],
+            InputAction::OpenEraView => vec![vec![Key(KeyCode::O)]],
+            InputAction::OpenCombatLog => vec![vec![Key(KeyCode::L)]],
+            InputAction::ToggleVerboseFeed => vec![vec![Key(KeyCode::V)]],
+            InputAction::ToggleFullscreen => vec![vec![Key(KeyCode::F11)]],
+            InputAction::Back => vec![vec![Key(KeyCode::Escape)]],
+            InputAction::OpenControls => vec![vec![Key(KeyCode::K)]],
+            InputAction::SkipOpening => vec![vec![Key(KeyCode::Back)]],
+            InputAction::TestBuffHero => vec![vec![Key(KeyCode::M)]],
+            InputAction::TestDebuffEnemy => vec![vec![Key(KeyCode::N)]],
+        }
+    }
+}
+
+/// A key on the keyboard, a mouse button, or a button on any gamepad.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+}
+
+impl InputButton {
+    pub fn pressed(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<MouseButton>,
+        pads: &Input<GamepadButton>,
+    ) -> bool {
+        match self {
+            InputButton::Key(key) => keys.pressed(*key),
+            InputButton::Mouse(button) => buttons.pressed(*button),
+            InputButton::Pad(button_type) => pads
+                .get_pressed()
+                .any(|button| button.button_type == *button_type),
+        }
+    }
+
+    pub fn just_pressed(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<MouseButton>,
+        pads: &Input<GamepadButton>,
+    ) -> bool {
+        match self {
+            InputButton::Key(key) => keys.just_pressed(*key),
+            InputButton::Mouse(button) => buttons.just_pressed(*button),
+            InputButton::Pad(button_type) => pads
+                .get_just_pressed()
+                .any(|button| button.button_type == *button_type),
+        }
+    }
+
+    /// Takes the press of this button, on every gamepad, so that later systems don't see it.
+    pub fn clear_just_pressed(
+        &self,
+        keys: &mut Input<KeyCode>,
+        buttons: &mut Input<MouseButton>,
+        pads: &mut Input<GamepadButton>,
+    ) {
+        match self {
+            InputButton::Key(key) => {
+                keys.clear_just_pressed(*key);
+            }
+            InputButton::Mouse(button) => {
+                buttons.clear_just_pressed(*button);
+            }
+            InputButton::Pad(button_type) => {
+                let pressed = pads
+                    .get_just_pressed()
+                    .filter(|button| button.button_type == *button_type)
+                    .copied()
+                    .collect::<Vec<_>>();
+                for button in pressed {
+                    pads.clear_just_pressed(button);
+                }
+            }
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            InputButton::Key(key) => format!("{:?}", key),
+            InputButton::Mouse(button) => format!("Mouse {:?}", button),
+            InputButton::Pad(button) => format!("Pad {:?}", button),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InputConfigLoader;
+
+impl AssetLoader for InputConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let custom_asset = ron::de::from_bytes::<InputConfig>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(custom_asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["input.ron"]
+    }
+}
@@ -2,6 +2,8 @@ use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
 
 use serde::{Deserialize, Serialize};
 
+use crate::game::StatBonus;
+
 use bevy::reflect::TypeUuid;
 #[derive(Debug, Deserialize, Serialize, Default, Clone, TypeUuid)]
 #[serde(deny_unknown_fields)]
@@ -14,6 +16,14 @@ pub struct SimConfig {
     pub chance_corridor: f32,
     pub chance_empty: f32,
     pub chance_fight: f32,
+    /// If true, every combat round posts a breakdown of the dice rolls to the dungeon feed.
+    /// Can be toggled in game with `V`.
+    pub verbose_combat_feed: bool,
+    /// If set, these stats are added to every enemy once for each level of depth it lives at.
+    pub enemy_scaling: Option<StatBonus>,
+    /// Items set to be used when the hero is hurt are used once the hero drops below this fraction
+    /// of max health: between 0 and 1.
+    pub auto_use_health_threshold: f32,
 }
 
 #[derive(Default)]
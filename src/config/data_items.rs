@@ -38,12 +38,17 @@ impl AssetLoader for ItemsDataLoader {
     ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
             let mut custom_asset = ron::de::from_bytes::<ItemsData>(bytes)?;
-            // HACK: force every items occupy one grid
-            custom_asset.items = custom_asset
-                .items
-                .into_iter()
-                .map(|(_, item)| (Dimens::unit(), item))
-                .collect();
+            for (dimens, item) in custom_asset.items.iter_mut() {
+                if let Some(shape) = item.shape {
+                    if shape.dimens() != *dimens {
+                        return Err(bevy::asset::Error::msg(format!(
+                            "The shape of {:?} doesn't match its dimensions {:?}.",
+                            item.id, dimens.0
+                        )));
+                    }
+                }
+                item.dimens = *dimens;
+            }
             load_context.set_default_asset(LoadedAsset::new(custom_asset));
             Ok(())
         })
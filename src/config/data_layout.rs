@@ -6,7 +6,7 @@ use bevy::reflect::TypeUuid;
 use serde::{Deserialize, Serialize};
 
 use crate::game::items::EquipmentSlot;
-use crate::positioning::Coords;
+use crate::positioning::{Coords, Dimens};
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone, TypeUuid)]
 #[serde(deny_unknown_fields)]
@@ -63,6 +63,12 @@ pub struct ColumnMiddle {
     pub width: f32,
     pub toasts: Container,
     pub inventory: Container,
+    /// Size of the backpack grid at the start of a run, in cells.
+    pub inventory_grid: Dimens,
+    /// Backpack upgrades can't grow the grid past this size.
+    pub max_inventory_grid: Dimens,
+    /// Size of a cell in the side-by-side view of both eras' backpacks, in points.
+    pub era_view_cell: f32,
 }
 
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
@@ -71,7 +77,12 @@ pub struct ColumnRight {
     pub margin_right: f32,
     pub combine_button: Container,
     pub crafting: Container,
+    /// Position of the crafting grid relative to the bottom-left cell of the backpack grid, and
+    /// its size, in cells.
+    pub crafting_grid: Coords,
     pub hero: Container,
+    /// The slots on the hero widget that equipped items go in.
+    pub equipment_grid: EquipmentGrid,
 }
 
 impl ColumnRight {
@@ -122,12 +133,27 @@ impl Container {
 
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct EquipmentGrid {
-    /// The absolute coordinates of the equipment grid. Coordinates of each of the individual slots
-    /// are relative to this.
+    /// The coordinates of the equipment grid, relative to the bottom-left cell of the backpack
+    /// grid. Coordinates of each of the individual slots are relative to this.
     pub coords: Coords,
     pub slots: HashMap<EquipmentSlot, Coords>,
 }
 
+impl EquipmentGrid {
+    /// The coordinates of every slot, relative to the bottom-left cell of the backpack grid.
+    pub fn slot_coords(&self) -> HashMap<EquipmentSlot, Coords> {
+        self.slots
+            .iter()
+            .map(|(slot, coords)| {
+                (
+                    *slot,
+                    Coords::new(self.coords.pos + coords.pos, coords.dimens),
+                )
+            })
+            .collect()
+    }
+}
+
 impl LayoutData {
     /// Returns the x position of the left column.
     pub fn left_x(&self) -> f32 {
@@ -1,5 +1,6 @@
 pub mod config_audio;
 pub mod config_debug;
+pub mod config_input;
 pub mod config_sim;
 pub mod data_blueprint;
 pub mod data_enemies;
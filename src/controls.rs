@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::config::config_input::{InputAction, InputButton, InputConfig};
+use crate::mouse::CURSOR_STAGE;
+
+/// Where the controls window saves the player's bindings. Files in the override directory are
+/// loaded instead of the ones in the default directory.
+const INPUT_OVERRIDE_PATH: &str = "assets/config/override/config.input.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputConfig>()
+            .init_resource::<ControlsWindow>()
+            .add_system_to_stage(CURSOR_STAGE, capture_binding)
+            .add_system(toggle_controls_window)
+            .add_system(draw_controls_window);
+    }
+}
+
+/// Resource. The window in which the player can rebind the controls.
+#[derive(Default)]
+pub struct ControlsWindow {
+    pub show_window: bool,
+    /// The action that the next buttons pressed will be bound to, and whether they are added to
+    /// its bindings rather than replacing them.
+    listening: Option<(InputAction, bool)>,
+    /// The buttons pressed so far while listening.
+    captured: Vec<InputButton>,
+    /// The outcome of the last save, shown at the bottom of the window.
+    status: String,
+}
+
+/// The OpenControls binding (`K` by default) opens and closes the controls window.
+fn toggle_controls_window(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut window: ResMut<ControlsWindow>,
+) {
+    if config.just_pressed(InputAction::OpenControls, &keys, &buttons, &pads) {
+        window.show_window = !window.show_window;
+    }
+}
+
+/// While the controls window waits for a new binding, every button that goes down is added to
+/// it, and none of them reach the rest of the game. Once they are all let go, they become the new
+/// binding.
+fn capture_binding(
+    mut window: ResMut<ControlsWindow>,
+    mut config: ResMut<InputConfig>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut buttons: ResMut<Input<MouseButton>>,
+    mut pads: ResMut<Input<GamepadButton>>,
+) {
+    let Some((action, add)) = window.listening else {
+        return;
+    };
+    let pressed = keys
+        .get_just_pressed()
+        .map(|key| InputButton::Key(*key))
+        .chain(
+            buttons
+                .get_just_pressed()
+                .map(|button| InputButton::Mouse(*button)),
+        )
+        .chain(
+            pads.get_just_pressed()
+                .map(|button| InputButton::Pad(button.button_type)),
+        )
+        .collect::<Vec<_>>();
+    for button in pressed {
+        button.clear_just_pressed(&mut keys, &mut buttons, &mut pads);
+        if !window.captured.contains(&button) {
+            window.captured.push(button);
+        }
+    }
+    if window.captured.is_empty()
+        || window
+            .captured
+            .iter()
+            .any(|button| button.pressed(&keys, &buttons, &pads))
+    {
+        return;
+    }
+    let binding = std::mem::take(&mut window.captured);
+    let mut bindings = if add {
+        config.bindings_for(action)
+    } else {
+        vec![]
+    };
+    if !bindings.contains(&binding) {
+        bindings.push(binding);
+    }
+    config.bindings.insert(action, bindings);
+    window.listening = None;
+}
+
+/// Lists every action with its bindings. Each can be replaced, added to or reset to its default,
+/// and the result saved to the override config.
+fn draw_controls_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut window: ResMut<ControlsWindow>,
+    mut config: ResMut<InputConfig>,
+) {
+    if !window.show_window {
+        return;
+    }
+    let mut show_window = window.show_window;
+    egui::Window::new("Controls")
+        .open(&mut show_window)
+        .default_width(480.)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.checkbox(
+                &mut config.click_to_drag,
+                "Click to pick items up and click again to put them down",
+            );
+            ui.separator();
+            egui::Grid::new("controls_grid")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for action in InputAction::ALL {
+                        ui.label(action.label());
+                        if window.listening.map(|(listening, _)| listening) == Some(action) {
+                            ui.label("Press the new buttons...");
+                        } else {
+                            let bindings = config
+                                .bindings_for(action)
+                                .iter()
+                                .map(|binding| {
+                                    binding
+                                        .iter()
+                                        .map(InputButton::label)
+                                        .collect::<Vec<_>>()
+                                        .join(" + ")
+                                })
+                                .collect::<Vec<_>>();
+                            ui.label(bindings.join(", "));
+                        }
+                        ui.horizontal(|ui| {
+                            let idle = window.listening.is_none();
+                            if ui.add_enabled(idle, egui::Button::new("Set")).clicked() {
+                                window.listening = Some((action, false));
+                            }
+                            if ui.add_enabled(idle, egui::Button::new("Add")).clicked() {
+                                window.listening = Some((action, true));
+                            }
+                            if ui.add_enabled(idle, egui::Button::new("Default")).clicked() {
+                                config.bindings.remove(&action);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    window.status = match save_input_config(&config) {
+                        Ok(()) => format!("Saved to {}", INPUT_OVERRIDE_PATH),
+                        Err(e) => format!("Could not save the controls: {}", e),
+                    };
+                }
+                ui.label(window.status.as_str());
+            });
+        });
+    window.show_window = show_window;
+    if !show_window {
+        window.listening = None;
+        window.captured.clear();
+    }
+}
+
+fn save_input_config(config: &InputConfig) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(config, default()).map_err(|e| e.to_string())?;
+    std::fs::write(INPUT_OVERRIDE_PATH, text).map_err(|e| e.to_string())
+}
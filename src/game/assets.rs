@@ -6,6 +6,7 @@ use bevy_kira_audio::AudioSource;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use crate::config::config_input::InputConfig;
 use crate::config::data_blueprint::BlueprintData;
 use crate::config::data_enemies::EnemiesData;
 use crate::config::data_items::ItemsData;
@@ -23,6 +24,7 @@ pub struct AssetStorage {
     fonts: HashMap<FontId, Handle<Font>>,
     pub audio: Handle<AudioConfig>,
     pub debug: Handle<DebugConfig>,
+    pub input: Handle<InputConfig>,
     pub sim: Handle<SimConfig>,
     pub blueprint: Handle<BlueprintData>,
     pub enemies: Handle<EnemiesData>,
@@ -150,6 +152,7 @@ impl AssetStorage {
         );
         vec.push(self.audio.clone().id);
         vec.push(self.debug.clone().id);
+        vec.push(self.input.clone().id);
         vec.push(self.sim.clone().id);
         vec.push(self.blueprint.clone().id);
         vec.push(self.enemies.clone().id);
@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+
+use crate::config::config_sim::SimConfig;
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::combat::{CombatRoundEvent, CombatState, Enemy, Hero};
+use crate::game::dungeon_sim::DungeonState;
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::item_info_system::TooltipBg;
+use crate::game::{use_item, CraftItem, FontId, Item, ItemStack};
+
+/// When the hero should use this item during dungeon combat, without the player clicking it.
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutoUsePolicy {
+    /// Use it once the hero's health drops below `SimConfig.auto_use_health_threshold`.
+    WhenHurt,
+    /// Use it as soon as a fight with a boss starts.
+    BeforeBossFight,
+}
+
+impl AutoUsePolicy {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AutoUsePolicy::WhenHurt => "when badly hurt",
+            AutoUsePolicy::BeforeBossFight => "before fighting a boss",
+        }
+    }
+}
+
+/// Only items that are used up on use can be set to auto-use. Equipment is equipped by hand.
+pub fn is_consumable(item: &Item) -> bool {
+    item.wearable.is_none()
+        && (item.temporary_effect.is_some()
+            || item.stat_bonuses.map(|s| s.health > 0).unwrap_or(false))
+}
+
+/// Only items that heal can be used for being hurt. Anything else would be used again every round,
+/// as it does nothing about the hero's health.
+pub fn restores_health(item: &Item) -> bool {
+    item.wearable.is_none() && item.stat_bonuses.map(|s| s.health > 0).unwrap_or(false)
+}
+
+/// Cycle the auto-use policy of a consumable: off, when hurt, before a boss fight. Items that don't
+/// heal skip "when hurt". The item context menu offers this for consumables.
+pub fn cycle_auto_use(
+    commands: &mut Commands,
+    feed: &mut EventWriter<AddFeedItemEvent>,
+    e: Entity,
+    item: &Item,
+    era: usize,
+    policy: Option<&AutoUsePolicy>,
+) {
+    let next = next_auto_use(item, policy);
+    let message = if let Some(next) = next {
+        commands.entity(e).insert(next);
+        format!(
+            "Sir Hoardalot will use the {} {}.",
+            item.name_in(era),
+            next.describe()
+        )
+    } else {
+        commands.entity(e).remove::<AutoUsePolicy>();
+        format!("Sir Hoardalot will leave the {} alone.", item.name_in(era))
+    };
+    feed.send(AddFeedItemEvent {
+        message,
+        colour: MessageColour::Neutral,
+        font: FontId::FiraSansItalic,
+    });
+}
+
+/// The auto-use policy of the item that comes after the given one.
+pub fn next_auto_use(item: &Item, policy: Option<&AutoUsePolicy>) -> Option<AutoUsePolicy> {
+    match policy {
+        None if restores_health(item) => Some(AutoUsePolicy::WhenHurt),
+        None => Some(AutoUsePolicy::BeforeBossFight),
+        Some(AutoUsePolicy::WhenHurt) => Some(AutoUsePolicy::BeforeBossFight),
+        Some(AutoUsePolicy::BeforeBossFight) => None,
+    }
+}
+
+/// During a fight, use the items in the current backpack whose auto-use policy says so.
+/// At most one item is used for being hurt per combat round, so that the hero doesn't down every
+/// flask in the backpack before the heal has had a chance to count.
+pub fn auto_use_consumables(
+    mut commands: Commands,
+    mut hero: ResMut<Hero>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+    mut boss_buffs_done: Local<bool>,
+    mut rounds: EventReader<CombatRoundEvent>,
+    config: Res<SimConfig>,
+    state: Res<DungeonState>,
+    enemy: Res<Enemy>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<
+        (Entity, &Item, &Backpack, &AutoUsePolicy, Option<&ItemStack>),
+        Without<CraftItem>,
+    >,
+    tooltips: Query<Entity, With<TooltipBg>>,
+) {
+    // Only look at the hero's health again once a round has been fought since the last check.
+    let round_fought = rounds.iter().count() > 0;
+    if state.combat_state != CombatState::InProgress {
+        *boss_buffs_done = false;
+        return;
+    }
+    let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+        return;
+    };
+    let hurt = round_fought
+        && (hero.combat_stats.health as f32)
+            < hero.combat_stats.max_health as f32 * config.auto_use_health_threshold;
+    let boss_fight_starting = enemy.boss && !*boss_buffs_done;
+    let mut used_healing = false;
+    for (e, item, backpack, policy, stack) in items.iter() {
+        if backpack.0 != *backpack_id {
+            continue;
+        }
+        let use_now = match policy {
+            AutoUsePolicy::WhenHurt => hurt && !used_healing && restores_health(item),
+            AutoUsePolicy::BeforeBossFight => boss_fight_starting,
+        };
+        if !use_now {
+            continue;
+        }
+        if *policy == AutoUsePolicy::WhenHurt {
+            used_healing = true;
+        }
+        debug!("Auto-using {}.", item.name_in(*backpack_id));
+        use_item(&mut commands, &mut hero, e, item, stack, &tooltips);
+        feed.send(AddFeedItemEvent {
+            message: format!("Sir Hoardalot uses the {}!", item.name_in(*backpack_id)),
+            colour: MessageColour::MinorPositive,
+            font: FontId::FiraSansMedium,
+        });
+    }
+    if boss_fight_starting {
+        *boss_buffs_done = true;
+    }
+}
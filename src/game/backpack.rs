@@ -1,6 +1,16 @@
 use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
 use iyes_loopless::prelude::{AppLooplessStateExt, ConditionSet};
 
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::config::data_layout::LayoutData;
+use crate::game::create_widget_grids::{create_grid, grid_button_position, InventoryGrid};
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::item_info_system::TooltipBg;
+use crate::game::sort_backpack::SortButton;
+use crate::game::{take_from_stack, AssetStorage, EquippedItem, FontId, ItemStack, StackItemEvent};
+use crate::mouse::MouseInteractive;
+use crate::positioning::{Dimens, GridData};
 use crate::states::AppState;
 
 use super::{create_widget_backpack::create_backpack_data, dungeon_sim::DungeonState, Item};
@@ -24,6 +34,10 @@ impl Plugin for BackpackPlugin {
                     .run_in_state(AppState::InGame)
                     .with_system(switch_backpack)
                     .with_system(show_in_backpack_items)
+                    .with_system(update_item_era_look)
+                    .with_system(use_backpack_upgrade)
+                    .with_system(resize_inventory_grid)
+                    .with_system(draw_overflow_window)
                     // .with_system(to_debug_backpack_switching)
                     .into(),
             );
@@ -37,13 +51,30 @@ pub struct BackpackInUse(pub usize);
 
 pub struct SwitchBackpackEvent(pub usize);
 
+/// Resource. Items that were meant to go into a backpack that had no room left for them. They
+/// wait here until the player takes them after making room, or throws them away.
+#[derive(Default)]
+pub struct BackpackOverflow {
+    pub items: Vec<OverflowItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverflowItem {
+    pub item: Item,
+    pub count: usize,
+    pub backpack: usize,
+}
+
 pub fn to_debug_backpack_switching(
     state: Res<DungeonState>,
-    input: Res<Input<KeyCode>>,
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
     backpack_in_use: Query<&BackpackInUse>,
     mut ew_switch_backpack: EventWriter<SwitchBackpackEvent>,
 ) {
-    if !state.running && input.just_pressed(KeyCode::B) {
+    if !state.running && config.just_pressed(InputAction::SwitchBackpack, &keys, &buttons, &pads) {
         let backpack_id = match backpack_in_use.get_single() {
             Ok(BackpackInUse(backpack_id)) => *backpack_id,
             Err(e) => {
@@ -99,3 +130,160 @@ pub fn show_in_backpack_items(
         vis.is_visible = backpack.0 == backpack_id;
     }
 }
+
+/// Items that move into another era's backpack, like equipment taken off there, take on the look
+/// they have in that era. Equipped items are worn in whichever era is in use, so they change their
+/// look whenever the hero switches backpacks.
+pub fn update_item_era_look(
+    assets: Res<AssetStorage>,
+    mut items: Query<(&Item, &Backpack, &mut Handle<Image>, &mut Name), Changed<Backpack>>,
+    backpack_in_use: Query<&BackpackInUse, Changed<BackpackInUse>>,
+    mut equipped: Query<
+        (&Item, &mut Handle<Image>, &mut Name),
+        (With<EquippedItem>, Without<Backpack>),
+    >,
+) {
+    for (item, backpack, mut texture, mut name) in items.iter_mut() {
+        *texture = assets.texture(&item.texture_in(backpack.0));
+        name.set(item.name_in(backpack.0).to_string());
+    }
+    let Ok(BackpackInUse(era)) = backpack_in_use.get_single() else {
+        return;
+    };
+    for (item, mut texture, mut name) in equipped.iter_mut() {
+        *texture = assets.texture(&item.texture_in(*era));
+        name.set(item.name_in(*era).to_string());
+    }
+}
+
+/// SHIFT-click a backpack upgrade to give the backpack more room. The grid grows for both eras.
+pub fn use_backpack_upgrade(
+    mut commands: Commands,
+    mut grid: ResMut<GridData>,
+    layout: Res<LayoutData>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+    items: Query<(Entity, &Item, &MouseInteractive, Option<&ItemStack>)>,
+    tooltips: Query<Entity, With<TooltipBg>>,
+) {
+    for (e, item, interactive, stack) in items.iter() {
+        let Some(upgrade) = item.backpack_upgrade else {
+            continue;
+        };
+        if !interactive.shift_clicked {
+            continue;
+        }
+        let message = upgrade_backpack(
+            &mut commands,
+            &mut grid,
+            &layout,
+            e,
+            upgrade,
+            stack,
+            &tooltips,
+        );
+        feed.send(AddFeedItemEvent {
+            message,
+            colour: MessageColour::Neutral,
+            font: FontId::FiraSansItalic,
+        });
+    }
+}
+
+/// Use up one backpack upgrade to grow the backpack, if it can still grow. Returns what happened,
+/// for the feed.
+pub fn upgrade_backpack(
+    commands: &mut Commands,
+    grid: &mut GridData,
+    layout: &LayoutData,
+    e: Entity,
+    upgrade: Dimens,
+    stack: Option<&ItemStack>,
+    tooltips: &Query<Entity, With<TooltipBg>>,
+) -> String {
+    if grid.grow_inventory(upgrade, layout.c_mid.max_inventory_grid) {
+        take_from_stack(commands, e, stack, 1);
+        for tooltip in tooltips.iter() {
+            commands.entity(tooltip).despawn_recursive();
+        }
+        format!(
+            "The backpack now holds {} by {} cells.",
+            grid.inventory.dimens.x, grid.inventory.dimens.y
+        )
+    } else {
+        "The backpack can't get any bigger.".to_string()
+    }
+}
+
+/// Redraw the backpack grid, and move the sort button along, when the backpack grows.
+pub fn resize_inventory_grid(
+    mut commands: Commands,
+    grid: Res<GridData>,
+    assets: Res<AssetStorage>,
+    old_grid: Query<Entity, With<InventoryGrid>>,
+    mut sort_button: Query<&mut Transform, With<SortButton>>,
+) {
+    if !grid.is_changed() || grid.is_added() {
+        return;
+    }
+    for e in old_grid.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    let new_grid = create_grid(&mut commands, &assets, &grid.inventory.dimens, grid.offset);
+    commands.entity(new_grid).insert(InventoryGrid);
+    let (x, y) = grid_button_position(grid.offset, grid.inventory.dimens.as_vec2());
+    for mut transform in sort_button.iter_mut() {
+        transform.translation.x = x;
+        transform.translation.y = y;
+    }
+}
+
+/// While anything is waiting in the overflow, list it so the player can take or discard it.
+pub fn draw_overflow_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut overflow: ResMut<BackpackOverflow>,
+    mut ew_stack_item: EventWriter<StackItemEvent>,
+) {
+    if overflow.items.is_empty() {
+        return;
+    }
+    let mut taken = None;
+    let mut discarded = None;
+    egui::Window::new("Backpack full")
+        .default_width(320.)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("There was no room for these. Make some space, then take them.");
+            ui.separator();
+            for (idx, overflowing) in overflow.items.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} x{}",
+                        overflowing.item.name_in(overflowing.backpack),
+                        overflowing.count
+                    ));
+                    if ui.button("Take").clicked() {
+                        taken = Some(idx);
+                    }
+                    if ui.button("Discard").clicked() {
+                        discarded = Some(idx);
+                    }
+                });
+            }
+        });
+    if let Some(idx) = taken {
+        // Whatever still doesn't fit comes straight back here.
+        let OverflowItem {
+            item,
+            count,
+            backpack,
+        } = overflow.items.remove(idx);
+        ew_stack_item.send(StackItemEvent {
+            item,
+            count,
+            backpack: Some(backpack),
+            source: None,
+            vacated: None,
+        });
+    } else if let Some(idx) = discarded {
+        overflow.items.remove(idx);
+    }
+}
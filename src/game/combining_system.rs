@@ -1,37 +1,46 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 use iyes_loopless::prelude::ConditionSet;
 
 use crate::audio::sound_event::SoundEvent;
 use crate::config::data_items::ItemsData;
 use crate::config::data_recipes::RecipesData;
-use crate::game::items::Item;
+use crate::game::items::{Item, ItemTag};
 use crate::game::recipes::Recipe;
-use crate::game::{find_free_space, ItemId, SoundId, SpawnItemEvent};
+use crate::game::{find_free_space, ItemId, SoundId, SpawnItemEvent, StackItemEvent};
 use crate::mouse::MouseInteractive;
-use crate::positioning::{Coords, Dimens, GridData};
+use crate::positioning::{Coords, GridData};
 use crate::states::AppState;
 
-use super::backpack::Backpack;
+use super::backpack::{Backpack, BackpackInUse, BackpackOverflow, OverflowItem};
 use super::dungeon_gen::TIMEPOINT_NOW;
 use super::dungeon_sim::{DungeonState, JumpTimepointEvent};
-use super::items::CraftItem;
+use super::items::{stack_count, CraftItem, ItemStack};
+use super::recipe_book::RecipeBook;
 
 #[derive(Component)]
 pub struct CombineButton {
     pub coords: Coords,
 }
 
+/// Marker component for the button that crafts the items in the crafting grid into a recipe.
+#[derive(Component)]
+pub struct CraftButton;
+
 pub struct EvolutionPlugin;
 
 impl Plugin for EvolutionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<EvolutionEvent>().add_system_set(
-            ConditionSet::new()
-                .run_in_state(AppState::InGame)
-                .with_system(evolution_after_jumped_timepoint)
-                .with_system(evolution)
-                .into(),
-        );
+        app.add_event::<EvolutionEvent>()
+            .init_resource::<LastEvolution>()
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(evolution_after_jumped_timepoint)
+                    .with_system(evolution)
+                    .into(),
+            );
     }
 }
 
@@ -40,6 +49,25 @@ pub struct EvolutionEvent {
     pub to: usize,
 }
 
+/// Resource. How the backpack that was evolved into changed in the last evolution.
+#[derive(Default)]
+pub struct LastEvolution {
+    pub backpack: Option<usize>,
+    /// Every item whose count changed, with its count before and after the evolution.
+    pub changes: Vec<(Item, usize, usize)>,
+}
+
+impl LastEvolution {
+    /// Whether the last evolution left more of this item in the given backpack than before.
+    pub fn gained(&self, backpack: usize, id: &ItemId) -> bool {
+        self.backpack == Some(backpack)
+            && self
+                .changes
+                .iter()
+                .any(|(item, before, after)| item.id == *id && after > before)
+    }
+}
+
 fn evolution_after_jumped_timepoint(
     mut jump: EventReader<JumpTimepointEvent>,
     mut evolution: EventWriter<EvolutionEvent>,
@@ -55,63 +83,116 @@ fn evolution_after_jumped_timepoint(
 fn evolution(
     mut evolution: EventReader<EvolutionEvent>,
     mut commands: Commands,
-    items: Query<(Entity, &Item, &Backpack, &Coords, Option<&CraftItem>)>,
+    items: Query<(
+        Entity,
+        &Item,
+        &Backpack,
+        &Coords,
+        Option<&CraftItem>,
+        Option<&ItemStack>,
+    )>,
     items_data: Res<ItemsData>,
     grid: Res<GridData>,
     mut spawn_event_writer: EventWriter<SpawnItemEvent>,
+    mut overflow: ResMut<BackpackOverflow>,
+    mut last_evolution: ResMut<LastEvolution>,
 ) {
     for &EvolutionEvent { from, to } in evolution.iter() {
         debug!("evolution from {}, to {}", from, to);
 
-        for (ent, _, _, _, _) in items
+        let mut counts = HashMap::<ItemId, (Item, usize, usize)>::new();
+        for (ent, item, _, _, _, stack) in items
             .iter()
-            .filter(|(_, _, backpack, _, _)| backpack.0 == to)
+            .filter(|(_, _, backpack, _, _, _)| backpack.0 == to)
         {
+            counts
+                .entry(item.id.clone())
+                .or_insert_with(|| (item.clone(), 0, 0))
+                .1 += stack_count(stack);
             commands.entity(ent).despawn();
         }
+        // Whatever was waiting to get into that backpack is gone along with it.
+        overflow
+            .items
+            .retain(|overflowing| overflowing.backpack != to);
 
         let from_coords = items
             .iter()
-            .filter(|(_, _, backpack, _, craft)| backpack.0 == from && craft.is_none())
-            .map(|(_, _, _, coords, _)| *coords)
+            .filter(|(_, _, backpack, _, craft, _)| backpack.0 == from && craft.is_none())
+            .map(|(_, _, _, coords, _, _)| *coords)
             .collect::<Vec<_>>();
         let craft_items = items
             .iter()
-            .filter(|(_, _, backpack, _, craft)| backpack.0 == from && craft.is_some())
-            .map(|(ent, item, _, _, _)| (ent, item))
+            .filter(|(_, _, backpack, _, craft, _)| backpack.0 == from && craft.is_some())
+            .map(|(ent, item, _, _, _, stack)| (ent, item, stack_count(stack)))
             .collect::<Vec<_>>();
         // items should contribute in evolution process
         let items_in_evo = craft_items
             .iter()
-            .map(|(_, item)| *item)
+            .map(|(_, item, count)| (*item, *count))
+            .collect::<Vec<_>>();
+        let new_items = calculate_items_after_evolution(&items_in_evo, &items_data)
+            .into_iter()
             .collect::<Vec<_>>();
-        let new_items = calculate_items_after_evolution(&items_in_evo, &items_data);
+        for (item, cnt) in new_items.iter() {
+            counts
+                .entry(item.id.clone())
+                .or_insert_with(|| (item.clone(), 0, 0))
+                .2 += cnt;
+        }
+        let mut changes = counts
+            .into_values()
+            .filter(|(_, before, after)| before != after)
+            .collect::<Vec<_>>();
+        changes.sort_by(|(a, _, _), (b, _, _)| a.name.cmp(&b.name));
+        *last_evolution = LastEvolution {
+            backpack: Some(to),
+            changes,
+        };
+
         let mut same_tick_items = vec![];
         let items_coords = vec![];
         for (item, cnt) in new_items.into_iter() {
-            for _ in 0..cnt {
-                if let Some(free_coords) =
-                    find_free_space(&grid, Dimens::unit(), &items_coords, &same_tick_items)
-                {
-                    spawn_event_writer.send(SpawnItemEvent::with_backpack(
-                        item.clone(),
-                        free_coords,
-                        grid.center_crafting(),
-                        to,
-                    ));
+            // The backpack that is evolved into starts out empty, so fill each stack to the brim.
+            for stack in item.split_into_stacks(cnt) {
+                if let Some(free_coords) = find_free_space(
+                    &grid,
+                    item.dimens,
+                    item.shape,
+                    &items_coords,
+                    &same_tick_items,
+                ) {
+                    spawn_event_writer.send(
+                        SpawnItemEvent::with_backpack(
+                            item.clone(),
+                            free_coords,
+                            grid.center_crafting(),
+                            to,
+                        )
+                        .stacked(stack),
+                    );
                     same_tick_items.push(free_coords);
                 } else {
-                    error!("Tried to find free space but failed.");
+                    overflow.items.push(OverflowItem {
+                        item: item.clone(),
+                        count: stack,
+                        backpack: to,
+                    });
                 }
             }
         }
 
         let mut same_tick_items = vec![];
-        for (ent, item) in craft_items {
-            if let Some(free_coords) =
-                find_free_space(&grid, Dimens::unit(), &from_coords, &same_tick_items)
-            {
-                let mut evt = SpawnItemEvent::without_anim(item.clone(), free_coords);
+        for (ent, item, count) in craft_items {
+            if let Some(free_coords) = find_free_space(
+                &grid,
+                item.dimens,
+                item.shape,
+                &from_coords,
+                &same_tick_items,
+            ) {
+                let mut evt =
+                    SpawnItemEvent::without_anim(item.clone(), free_coords).stacked(count);
                 evt.backpack = Some(from);
                 spawn_event_writer.send(evt);
                 same_tick_items.push(free_coords);
@@ -123,12 +204,22 @@ fn evolution(
     }
 }
 
-fn count_by_id<'a>(items: impl IntoIterator<Item = &'a &'a Item>, id: ItemId) -> usize {
-    items.into_iter().filter(|it| it.id == id).count()
+/// How many of the given item there are, counting every item on a stack.
+pub fn count_by_id(items: &[(&Item, usize)], id: ItemId) -> usize {
+    items
+        .iter()
+        .filter(|(it, _)| it.id == id)
+        .map(|(_, count)| count)
+        .sum()
 }
 
-fn contains<'a>(items: impl IntoIterator<Item = &'a &'a Item>, id: ItemId) -> bool {
-    items.into_iter().any(|it| it.id == id)
+/// How many items with the given tag there are, counting every item on a stack.
+pub fn count_by_tag(items: &[(&Item, usize)], tag: ItemTag) -> usize {
+    items
+        .iter()
+        .filter(|(it, _)| it.has_tag(tag))
+        .map(|(_, count)| count)
+        .sum()
 }
 
 fn increase_or_unlock(original: usize, add: usize, unlock: bool) -> usize {
@@ -139,288 +230,303 @@ fn increase_or_unlock(original: usize, add: usize, unlock: bool) -> usize {
     }
 }
 
-fn calculate_items_after_evolution<'a, T>(
-    // this should be items put inside 改變物品格s
-    items: &'a T,
+/// How one item of the evolution chain comes about and grows. `calculate_items_after_evolution`
+/// works through these rules, and the tech tree, the tooltips and the graph export show them.
+pub struct EvolutionRule {
+    pub item: ItemId,
+    /// Whether evolution can bring the item into an era that doesn't have it yet. Items that it
+    /// can't only come from the starting backpack.
+    pub unlockable: bool,
+    /// The items, and at least how many of each, that unlock this item. An unlockable item that
+    /// needs nothing turns up in every era.
+    pub unlocked_by: &'static [(ItemId, usize)],
+    /// How much the item grows by on every evolution, once the era has it.
+    pub growth: Growth,
+    /// The population the era has to be over on top of the items above, or 0.
+    pub population: usize,
+}
+
+/// How much an item of the evolution chain grows by on every evolution.
+pub enum Growth {
+    None,
+    /// By the `tool_tier` of the best `Tool` in the era.
+    BestTool,
+    /// By the number of these items together, divided by the given number and rounded down.
+    Count(&'static [ItemId], usize),
+    /// By the bitwise OR of the numbers of each of these items.
+    BitOr(&'static [ItemId]),
+}
+
+impl EvolutionRule {
+    /// Whether the items in an era, with its population, are enough to bring this item in.
+    pub fn is_unlocked(&self, items: &[(&Item, usize)], population: usize) -> bool {
+        self.unlockable
+            && self
+                .unlocked_by
+                .iter()
+                .all(|(id, count)| count_by_id(items, id.clone()) >= *count)
+            && (self.population == 0 || population > self.population)
+    }
+
+    /// The items this one grows with. Items that grow with the best tool grow with every `Tool`.
+    pub fn growth_sources(&self, items_data: &ItemsData) -> Vec<ItemId> {
+        match self.growth {
+            Growth::None => vec![],
+            Growth::BestTool => items_data
+                .items
+                .iter()
+                .filter(|(_, item)| item.has_tag(ItemTag::Tool))
+                .map(|(_, item)| item.id.clone())
+                .collect(),
+            Growth::Count(ids, _) | Growth::BitOr(ids) => ids.to_vec(),
+        }
+    }
+}
+
+impl Growth {
+    /// How much the item grows by, given the items in the era and the tier of its best tool.
+    fn amount(&self, items: &[(&Item, usize)], tool_tier: usize) -> usize {
+        match self {
+            Growth::None => 0,
+            Growth::BestTool => tool_tier,
+            Growth::Count(ids, per) => {
+                ids.iter()
+                    .map(|id| count_by_id(items, id.clone()))
+                    .sum::<usize>()
+                    / per
+            }
+            Growth::BitOr(ids) => ids
+                .iter()
+                .map(|id| count_by_id(items, id.clone()))
+                .fold(0, |acc, count| acc | count),
+        }
+    }
+}
+
+/// The items that make up the population of an era, with how many people each of them feeds.
+pub const POPULATION_SOURCES: &[(ItemId, usize)] = &[
+    (ItemId::Wheat, 200),
+    (ItemId::Meat, 400),
+    (ItemId::Fish, 300),
+];
+
+const fn rule(
+    item: ItemId,
+    unlocked_by: &'static [(ItemId, usize)],
+    growth: Growth,
+    population: usize,
+) -> EvolutionRule {
+    EvolutionRule {
+        item,
+        unlockable: true,
+        unlocked_by,
+        growth,
+        population,
+    }
+}
+
+/// A rule for an item that only comes from the starting backpack.
+const fn starting_item(item: ItemId, growth: Growth) -> EvolutionRule {
+    EvolutionRule {
+        item,
+        unlockable: false,
+        unlocked_by: &[],
+        growth,
+        population: 0,
+    }
+}
+
+pub const EVOLUTION_RULES: &[EvolutionRule] = &[
+    rule(ItemId::Wheat, &[], Growth::BestTool, 0),
+    rule(
+        ItemId::Alcohol,
+        &[(ItemId::Wheat, 3)],
+        Growth::Count(&[ItemId::Wheat], 3),
+        0,
+    ),
+    rule(
+        ItemId::Meat,
+        &[(ItemId::GatheringAndHunting, 1)],
+        Growth::BestTool,
+        0,
+    ),
+    rule(ItemId::Fish, &[(ItemId::Fishery, 1)], Growth::BestTool, 0),
+    starting_item(ItemId::StoneTool, Growth::Count(&[ItemId::Chiefdom], 1)),
+    rule(
+        ItemId::BronzeTool,
+        &[(ItemId::StoneTool, 2)],
+        Growth::Count(&[ItemId::Religion], 1),
+        0,
+    ),
+    rule(
+        ItemId::IronTool,
+        &[(ItemId::BronzeTool, 3)],
+        Growth::Count(&[ItemId::Feudal], 1),
+        0,
+    ),
+    rule(
+        ItemId::SteelTool,
+        &[(ItemId::IronTool, 4)],
+        Growth::BitOr(&[ItemId::Democracy, ItemId::Centralization]),
+        0,
+    ),
+    rule(
+        ItemId::SteamPower,
+        &[(ItemId::SteelTool, 6)],
+        Growth::BitOr(&[
+            ItemId::Theocracy,
+            ItemId::Empire,
+            ItemId::Totalitarian,
+            ItemId::PermanentMember,
+        ]),
+        0,
+    ),
+    rule(
+        ItemId::ElectronicTechnology,
+        &[(ItemId::SteamPower, 6)],
+        Growth::None,
+        0,
+    ),
+    rule(
+        ItemId::Chiefdom,
+        &[(ItemId::Wheat, 3)],
+        Growth::Count(&[ItemId::Wheat], 3),
+        0,
+    ),
+    rule(
+        ItemId::Religion,
+        &[(ItemId::Alcohol, 1), (ItemId::Fish, 1), (ItemId::Meat, 1)],
+        Growth::None,
+        0,
+    ),
+    rule(
+        ItemId::Theocracy,
+        &[(ItemId::Religion, 2), (ItemId::Book, 2)],
+        Growth::None,
+        2000,
+    ),
+    rule(
+        ItemId::Feudal,
+        &[(ItemId::Chiefdom, 1), (ItemId::Writing, 1)],
+        Growth::None,
+        1000,
+    ),
+    rule(
+        ItemId::Monarchy,
+        &[(ItemId::Chiefdom, 2)],
+        Growth::Count(&[ItemId::Chiefdom], 5),
+        2000,
+    ),
+    rule(
+        ItemId::Empire,
+        &[
+            (ItemId::Monarchy, 2),
+            (ItemId::Centralization, 1),
+            (ItemId::Book, 1),
+        ],
+        Growth::None,
+        2000,
+    ),
+    rule(
+        ItemId::Centralization,
+        &[(ItemId::Monarchy, 2)],
+        Growth::None,
+        3000,
+    ),
+    rule(
+        ItemId::Totalitarian,
+        &[
+            (ItemId::Centralization, 1),
+            (ItemId::Printing, 1),
+            (ItemId::SteamPower, 1),
+        ],
+        Growth::None,
+        2000,
+    ),
+    rule(
+        ItemId::Democracy,
+        &[(ItemId::Trading, 1), (ItemId::Book, 1), (ItemId::Wheat, 2)],
+        Growth::None,
+        0,
+    ),
+    rule(
+        ItemId::PermanentMember,
+        &[(ItemId::Democracy, 1), (ItemId::Trading, 3)],
+        Growth::None,
+        2000,
+    ),
+    rule(
+        ItemId::Writing,
+        &[(ItemId::Religion, 1), (ItemId::StoneTool, 1)],
+        Growth::Count(&[ItemId::StoneTool], 1),
+        0,
+    ),
+    rule(
+        ItemId::Book,
+        &[(ItemId::Monarchy, 1), (ItemId::BronzeTool, 1)],
+        Growth::Count(&[ItemId::BronzeTool], 1),
+        0,
+    ),
+    rule(
+        ItemId::Printing,
+        &[(ItemId::Monarchy, 1), (ItemId::IronTool, 1)],
+        Growth::Count(&[ItemId::IronTool], 1),
+        0,
+    ),
+    rule(
+        ItemId::Currency,
+        &[(ItemId::Feudal, 1), (ItemId::BronzeTool, 1)],
+        Growth::Count(&[ItemId::BronzeTool], 1),
+        0,
+    ),
+    starting_item(ItemId::GatheringAndHunting, Growth::None),
+    starting_item(ItemId::Fishery, Growth::None),
+    rule(
+        ItemId::Trading,
+        &[(ItemId::Monarchy, 1), (ItemId::Currency, 5)],
+        Growth::None,
+        0,
+    ),
+    rule(
+        ItemId::Industrialization,
+        &[(ItemId::SteamPower, 5)],
+        Growth::None,
+        0,
+    ),
+];
+
+fn calculate_items_after_evolution(
+    // this should be items put inside 改變物品格s, with the size of their stacks
+    items: &[(&Item, usize)],
     items_data: &ItemsData,
-) -> impl IntoIterator<Item = (Item, usize)>
-where
-    &'a T: IntoIterator<Item = &'a &'a Item>,
-{
-    let mut v = vec![];
+) -> impl IntoIterator<Item = (Item, usize)> {
     let get_item = |id: ItemId| {
         let item = items_data.try_get_item(id.clone()).unwrap_or_default().1;
         item
     };
-    let tool_points = [
-        (ItemId::ElectronicTechnology, 6),
-        (ItemId::SteamPower, 5),
-        (ItemId::SteelTool, 4),
-        (ItemId::IronTool, 3),
-        (ItemId::BronzeTool, 2),
-        (ItemId::StoneTool, 1),
-    ]
-    .into_iter()
-    .filter(|(id, _)| contains(items, id.clone()))
-    .next()
-    .map(|(_, point)| point)
-    .unwrap_or(0);
-    // TODO: create a Resource for this
-    let population = count_by_id(items, ItemId::Wheat) * 200
-        + count_by_id(items, ItemId::Meat) * 400
-        + count_by_id(items, ItemId::Fish) * 300;
-
-    // update wheat
-    v.push((
-        get_item(ItemId::Wheat),
-        increase_or_unlock(count_by_id(items, ItemId::Wheat), tool_points, true),
-    ));
-    // update alcohol
-    v.push((
-        get_item(ItemId::Alcohol),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Alcohol),
-            count_by_id(items, ItemId::Wheat) / 3,
-            count_by_id(items, ItemId::Wheat) > 2,
-        ),
-    ));
-    // update meat
-    v.push((
-        get_item(ItemId::Meat),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Meat),
-            tool_points,
-            count_by_id(items, ItemId::GatheringAndHunting) > 0,
-        ),
-    ));
-    // update fish
-    v.push((
-        get_item(ItemId::Fish),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Fish),
-            tool_points,
-            count_by_id(items, ItemId::Fishery) > 0,
-        ),
-    ));
-
-    // update stone tool
-    v.push((
-        get_item(ItemId::StoneTool),
-        increase_or_unlock(
-            count_by_id(items, ItemId::StoneTool),
-            count_by_id(items, ItemId::Chiefdom),
-            false,
-        ),
-    ));
-    // update bronze tool
-    v.push((
-        get_item(ItemId::BronzeTool),
-        increase_or_unlock(
-            count_by_id(items, ItemId::BronzeTool),
-            count_by_id(items, ItemId::Religion),
-            count_by_id(items, ItemId::StoneTool) > 1,
-        ),
-    ));
-    // update iron tool
-    v.push((
-        get_item(ItemId::IronTool),
-        increase_or_unlock(
-            count_by_id(items, ItemId::IronTool),
-            count_by_id(items, ItemId::Feudal),
-            count_by_id(items, ItemId::BronzeTool) > 2,
-        ),
-    ));
-    // update steel tool
-    v.push((
-        get_item(ItemId::SteelTool),
-        increase_or_unlock(
-            count_by_id(items, ItemId::SteelTool),
-            count_by_id(items, ItemId::Democracy) | count_by_id(items, ItemId::Centralization),
-            count_by_id(items, ItemId::IronTool) > 3,
-        ),
-    ));
-    // update steam tool
-    v.push((
-        get_item(ItemId::SteamPower),
-        increase_or_unlock(
-            count_by_id(items, ItemId::SteamPower),
-            count_by_id(items, ItemId::Theocracy)
-                | count_by_id(items, ItemId::Empire)
-                | count_by_id(items, ItemId::Totalitarian)
-                | count_by_id(items, ItemId::PermanentMember),
-            count_by_id(items, ItemId::SteelTool) > 5,
-        ),
-    ));
-    // update eletronic tool
-    v.push((
-        get_item(ItemId::ElectronicTechnology),
-        increase_or_unlock(
-            count_by_id(items, ItemId::ElectronicTechnology),
-            0,
-            count_by_id(items, ItemId::SteamPower) > 5,
-        ),
-    ));
-
-    v.push((
-        get_item(ItemId::Chiefdom),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Chiefdom),
-            count_by_id(items, ItemId::Wheat) / 3,
-            count_by_id(items, ItemId::Wheat) > 2,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Religion),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Religion),
-            0,
-            count_by_id(items, ItemId::Alcohol) > 0
-                && count_by_id(items, ItemId::Fish) > 0
-                && count_by_id(items, ItemId::Meat) > 0,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Theocracy),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Theocracy),
-            0,
-            count_by_id(items, ItemId::Religion) > 1
-                && count_by_id(items, ItemId::Book) > 1
-                && population > 2000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Feudal),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Feudal),
-            0,
-            count_by_id(items, ItemId::Chiefdom) > 0
-                && count_by_id(items, ItemId::Writing) > 0
-                && population > 1000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Monarchy),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Monarchy),
-            count_by_id(items, ItemId::Chiefdom) / 5,
-            count_by_id(items, ItemId::Chiefdom) > 1 && population > 2000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Empire),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Empire),
-            0,
-            count_by_id(items, ItemId::Monarchy) > 1
-                && count_by_id(items, ItemId::Centralization) > 0
-                && count_by_id(items, ItemId::Book) > 0
-                && population > 2000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Centralization),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Centralization),
-            0,
-            count_by_id(items, ItemId::Monarchy) > 1 && population > 3000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Totalitarian),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Totalitarian),
-            0,
-            count_by_id(items, ItemId::Centralization) > 0
-                && count_by_id(items, ItemId::Printing) > 0
-                && count_by_id(items, ItemId::SteamPower) > 0
-                && population > 2000,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Democracy),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Democracy),
-            0,
-            count_by_id(items, ItemId::Trading) > 0
-                && count_by_id(items, ItemId::Book) > 0
-                && count_by_id(items, ItemId::Wheat) > 1,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::PermanentMember),
-        increase_or_unlock(
-            count_by_id(items, ItemId::PermanentMember),
-            0,
-            count_by_id(items, ItemId::Democracy) > 0
-                && count_by_id(items, ItemId::Trading) > 2
-                && population > 2000,
-        ),
-    ));
-
-    // update writing
-    v.push((
-        get_item(ItemId::Writing),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Writing),
-            count_by_id(items, ItemId::StoneTool),
-            count_by_id(items, ItemId::Religion) > 0 && count_by_id(items, ItemId::StoneTool) > 0,
-        ),
-    ));
-    // update book
-    v.push((
-        get_item(ItemId::Book),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Book),
-            count_by_id(items, ItemId::BronzeTool),
-            count_by_id(items, ItemId::Monarchy) > 0 && count_by_id(items, ItemId::BronzeTool) > 0,
-        ),
-    ));
-    // update printing
-    v.push((
-        get_item(ItemId::Printing),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Printing),
-            count_by_id(items, ItemId::IronTool),
-            count_by_id(items, ItemId::Monarchy) > 0 && count_by_id(items, ItemId::IronTool) > 0,
-        ),
-    ));
-
-    v.push((
-        get_item(ItemId::Currency),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Currency),
-            count_by_id(items, ItemId::BronzeTool),
-            count_by_id(items, ItemId::Feudal) > 0 && count_by_id(items, ItemId::BronzeTool) > 0,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::GatheringAndHunting),
-        increase_or_unlock(count_by_id(items, ItemId::GatheringAndHunting), 0, false),
-    ));
-    v.push((
-        get_item(ItemId::Fishery),
-        increase_or_unlock(count_by_id(items, ItemId::Fishery), 0, false),
-    ));
-    v.push((
-        get_item(ItemId::Trading),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Trading),
-            0,
-            count_by_id(items, ItemId::Monarchy) > 0 && count_by_id(items, ItemId::Currency) > 4,
-        ),
-    ));
-    v.push((
-        get_item(ItemId::Industrialization),
-        increase_or_unlock(
-            count_by_id(items, ItemId::Industrialization),
-            0,
-            count_by_id(items, ItemId::SteamPower) > 4,
-        ),
-    ));
-
-    // filter items that appear zero times
-    v.into_iter()
+    // The best tool in the era decides how fast food is gathered.
+    let tool_tier = items
+        .iter()
+        .filter(|(it, _)| it.has_tag(ItemTag::Tool))
+        .map(|(it, _)| it.tool_tier)
+        .max()
+        .unwrap_or(0);
+    let population = POPULATION_SOURCES
+        .iter()
+        .map(|(id, people)| count_by_id(items, id.clone()) * people)
+        .sum::<usize>();
+
+    EVOLUTION_RULES
+        .iter()
+        .map(|rule| {
+            let count = increase_or_unlock(
+                count_by_id(items, rule.item.clone()),
+                rule.growth.amount(items, tool_tier),
+                rule.is_unlocked(items, population),
+            );
+            (get_item(rule.item.clone()), count)
+        })
+        // filter items that appear zero times
         .filter(|(_, cnt)| *cnt != 0)
         .collect::<Vec<_>>()
 }
@@ -432,7 +538,7 @@ pub fn combine_items_system(
     mut audio: EventWriter<SoundEvent>,
     grid: Res<GridData>,
     combine_button_query: Query<&MouseInteractive, With<CombineButton>>,
-    crafting_items_query: Query<(Entity, &Item), With<CraftItem>>,
+    crafting_items_query: Query<(Entity, &Item, Option<&ItemStack>), With<CraftItem>>,
     items_query: Query<(&Coords, &Backpack), With<Item>>,
     mut state: ResMut<DungeonState>,
     mut ew_jump: EventWriter<JumpTimepointEvent>,
@@ -456,16 +562,23 @@ pub fn combine_items_system(
                     .map(|(coords, _)| *coords)
                     .collect::<Vec<_>>();
                 let mut same_tick_items = vec![];
-                for (ent, item) in crafting_items_query.iter() {
-                    if let Some(free_coords) =
-                        find_free_space(&grid, Dimens::unit(), &items_coords, &same_tick_items)
-                    {
-                        spawn_event_writer.send(SpawnItemEvent::with_backpack(
-                            item.clone(),
-                            free_coords,
-                            grid.center_crafting(),
-                            to,
-                        ));
+                for (ent, item, stack) in crafting_items_query.iter() {
+                    if let Some(free_coords) = find_free_space(
+                        &grid,
+                        item.dimens,
+                        item.shape,
+                        &items_coords,
+                        &same_tick_items,
+                    ) {
+                        spawn_event_writer.send(
+                            SpawnItemEvent::with_backpack(
+                                item.clone(),
+                                free_coords,
+                                grid.center_crafting(),
+                                to,
+                            )
+                            .stacked(stack_count(stack)),
+                        );
                         same_tick_items.push(free_coords);
                         commands.entity(ent).despawn_recursive();
                     } else {
@@ -483,31 +596,85 @@ pub fn combine_items_system(
     }
 }
 
-pub fn try_get_recipe(data: &RecipesData, items: &Vec<Item>) -> Option<Recipe> {
-    let mut possible_recipe: Option<Recipe> = None;
-
-    let mut flat_recipe = Vec::<ItemId>::new();
-    let items_ids: Vec<ItemId> = items.into_iter().map(|f| f.id.clone()).collect();
+/// Click the craft button to turn the items in the crafting grid into the result of the recipe
+/// they make up. The ingredients are used up, and the result appears in the crafting grid.
+pub fn craft_items_system(
+    mut commands: Commands,
+    mut spawn_event_writer: EventWriter<SpawnItemEvent>,
+    mut stack_event_writer: EventWriter<StackItemEvent>,
+    mut audio: EventWriter<SoundEvent>,
+    grid: Res<GridData>,
+    recipes: Res<RecipesData>,
+    items_data: Res<ItemsData>,
+    mut recipe_book: ResMut<RecipeBook>,
+    craft_button_query: Query<&MouseInteractive, With<CraftButton>>,
+    crafting_items_query: Query<(Entity, &Item, &Backpack, Option<&ItemStack>), With<CraftItem>>,
+    backpack_in_use: Query<&BackpackInUse>,
+) {
+    let Ok(craft_button) = craft_button_query.get_single() else {
+        return;
+    };
+    if !craft_button.clicked {
+        return;
+    }
+    let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+        error!("There should be only one BackpackInUse component in game.");
+        return;
+    };
+    let ingredients = crafting_items_query
+        .iter()
+        .filter(|(_, _, backpack, _)| backpack.0 == *backpack_id)
+        .collect::<Vec<_>>();
+    let placed = ingredients
+        .iter()
+        .map(|(_, item, _, stack)| (*item, stack_count(*stack)))
+        .collect::<Vec<_>>();
+    let Some(recipe) = try_get_recipe(&recipes, &placed) else {
+        audio.send(SoundEvent::Sfx(SoundId::CombineCant));
+        return;
+    };
+    let Some((_, result)) = items_data.try_get_item(recipe.result.clone()) else {
+        audio.send(SoundEvent::Sfx(SoundId::CombineCant));
+        return;
+    };
 
-    for recipe in &data.recipes {
-        flat_recipe.clear();
-        for ingr in &recipe.ingredients {
-            for _ in 0..(ingr.quantity) {
-                flat_recipe.push(ingr.item_id.clone());
-            }
-        }
-        let difference: Vec<_> = items_ids
-            .clone()
-            .into_iter()
-            .filter(|item| !flat_recipe.contains(item))
-            .collect();
-        if difference.len() == 0 {
-            possible_recipe = Option::from(recipe.clone());
-            break;
-        }
+    for (ent, _, _, _) in ingredients.iter() {
+        commands.entity(*ent).despawn_recursive();
+    }
+    // Everything in the crafting grid is used up, so the result can go in its corner.
+    let coords = result.footprint_at(grid.crafting.pos);
+    if grid.crafting.encloses(&coords) {
+        let mut evt = SpawnItemEvent::new(result.clone(), coords, grid.center_crafting(), true);
+        evt.backpack = Some(*backpack_id);
+        spawn_event_writer.send(evt);
+    } else {
+        stack_event_writer.send(StackItemEvent {
+            item: result.clone(),
+            count: 1,
+            backpack: Some(*backpack_id),
+            source: Some(grid.center_crafting()),
+            vacated: None,
+        });
     }
+    audio.send(SoundEvent::Sfx(SoundId::CombineSmithing));
+    recipe_book.discover(&recipe);
+    info!("Crafted {}.", result.name);
+}
 
-    possible_recipe
+/// Finds the recipe that the given items make up exactly: each of its ingredients in the right
+/// quantity, and nothing else besides.
+pub fn try_get_recipe(data: &RecipesData, items: &[(&Item, usize)]) -> Option<Recipe> {
+    if items.is_empty() {
+        return None;
+    }
+    let mut placed = HashMap::<ItemId, usize>::new();
+    for (item, count) in items {
+        *placed.entry(item.id.clone()).or_default() += count;
+    }
+    data.recipes
+        .iter()
+        .find(|recipe| recipe.ingredient_counts() == placed)
+        .cloned()
 }
 
 pub fn update_label_for_combine_button(
@@ -531,3 +698,159 @@ pub fn update_label_for_combine_button(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::recipes::Ingredient;
+    use crate::positioning::Dimens;
+
+    fn item(id: ItemId) -> Item {
+        Item { id, ..default() }
+    }
+
+    fn tool(id: ItemId, tool_tier: usize) -> Item {
+        Item {
+            tags: vec![ItemTag::Tool],
+            tool_tier,
+            ..item(id)
+        }
+    }
+
+    /// Every item of the evolution chain, where only the stone tool is a `Tool`.
+    fn items_data() -> ItemsData {
+        let data_item = |id: &ItemId| match id {
+            ItemId::StoneTool => tool(id.clone(), 1),
+            _ => item(id.clone()),
+        };
+        ItemsData {
+            items: EVOLUTION_RULES
+                .iter()
+                .map(|rule| (Dimens::unit(), data_item(&rule.item)))
+                .collect(),
+        }
+    }
+
+    /// Runs one evolution over the given items, and counts what comes out by item.
+    fn evolve(items: &[(Item, usize)]) -> HashMap<ItemId, usize> {
+        let items = items
+            .iter()
+            .map(|(it, count)| (it, *count))
+            .collect::<Vec<_>>();
+        calculate_items_after_evolution(&items, &items_data())
+            .into_iter()
+            .map(|(it, count)| (it.id, count))
+            .collect()
+    }
+
+    #[test]
+    fn wheat_turns_up_in_an_empty_era() {
+        assert_eq!(evolve(&[]), HashMap::from([(ItemId::Wheat, 1)]));
+    }
+
+    #[test]
+    fn food_grows_with_the_best_tool() {
+        let after = evolve(&[
+            (item(ItemId::Wheat), 1),
+            (tool(ItemId::StoneTool, 2), 1),
+            (tool(ItemId::BronzeTool, 3), 1),
+        ]);
+        assert_eq!(after[&ItemId::Wheat], 4);
+        assert_eq!(after[&ItemId::StoneTool], 1);
+        assert_eq!(after[&ItemId::BronzeTool], 1);
+    }
+
+    #[test]
+    fn unlocks_need_every_listed_item() {
+        let after = evolve(&[(item(ItemId::Wheat), 3)]);
+        assert_eq!(after[&ItemId::Alcohol], 1);
+        assert_eq!(after[&ItemId::Chiefdom], 1);
+        let after = evolve(&[(item(ItemId::Wheat), 2)]);
+        assert!(!after.contains_key(&ItemId::Alcohol));
+        assert!(!after.contains_key(&ItemId::Chiefdom));
+    }
+
+    #[test]
+    fn unlocks_can_need_a_population() {
+        // Every wheat feeds 200 people, and a monarchy needs more than 2000.
+        let after = evolve(&[(item(ItemId::Chiefdom), 2), (item(ItemId::Wheat), 10)]);
+        assert!(!after.contains_key(&ItemId::Monarchy));
+        let after = evolve(&[(item(ItemId::Chiefdom), 2), (item(ItemId::Wheat), 11)]);
+        assert_eq!(after[&ItemId::Monarchy], 1);
+    }
+
+    #[test]
+    fn growth_counts_and_ors_other_items() {
+        let after = evolve(&[
+            (item(ItemId::Chiefdom), 2),
+            (item(ItemId::Wheat), 7),
+            (item(ItemId::SteelTool), 1),
+            (item(ItemId::Democracy), 1),
+            (item(ItemId::Centralization), 2),
+        ]);
+        assert_eq!(after[&ItemId::Chiefdom], 2 + 7 / 3);
+        assert_eq!(after[&ItemId::SteelTool], 1 + (1 | 2));
+    }
+
+    #[test]
+    fn starting_items_are_never_unlocked() {
+        let after = evolve(&[(item(ItemId::Wheat), 50), (item(ItemId::Chiefdom), 5)]);
+        assert!(!after.contains_key(&ItemId::StoneTool));
+        assert!(!after.contains_key(&ItemId::GatheringAndHunting));
+        assert!(!after.contains_key(&ItemId::Fishery));
+    }
+
+    #[test]
+    fn best_tool_growth_comes_from_every_tool() {
+        let wheat = EVOLUTION_RULES
+            .iter()
+            .find(|rule| rule.item == ItemId::Wheat)
+            .unwrap();
+        assert_eq!(wheat.growth_sources(&items_data()), vec![ItemId::StoneTool]);
+    }
+
+    fn recipe(result: ItemId, ingredients: &[(ItemId, i32)]) -> Recipe {
+        Recipe {
+            result,
+            ingredients: ingredients
+                .iter()
+                .map(|(item_id, quantity)| Ingredient {
+                    item_id: item_id.clone(),
+                    quantity: *quantity,
+                })
+                .collect(),
+        }
+    }
+
+    fn recipes() -> RecipesData {
+        RecipesData {
+            recipes: vec![
+                recipe(ItemId::Vial, &[(ItemId::Wheat, 2), (ItemId::Fish, 1)]),
+                recipe(ItemId::Meat, &[(ItemId::Fish, 3)]),
+            ],
+        }
+    }
+
+    #[test]
+    fn finds_exact_matches() {
+        let (wheat, fish) = (item(ItemId::Wheat), item(ItemId::Fish));
+        let found = try_get_recipe(&recipes(), &[(&wheat, 2), (&fish, 1)]).unwrap();
+        assert_eq!(found.result, ItemId::Vial);
+    }
+
+    #[test]
+    fn adds_up_stacks_of_the_same_item() {
+        let fish = item(ItemId::Fish);
+        let found = try_get_recipe(&recipes(), &[(&fish, 1), (&fish, 2)]).unwrap();
+        assert_eq!(found.result, ItemId::Meat);
+    }
+
+    #[test]
+    fn rejects_missing_extra_or_no_ingredients() {
+        let (wheat, fish, meat) = (item(ItemId::Wheat), item(ItemId::Fish), item(ItemId::Meat));
+        assert!(try_get_recipe(&recipes(), &[(&wheat, 2)]).is_none());
+        assert!(try_get_recipe(&recipes(), &[(&wheat, 3), (&fish, 1)]).is_none());
+        assert!(try_get_recipe(&recipes(), &[(&wheat, 2), (&fish, 1), (&meat, 1)]).is_none());
+        assert!(try_get_recipe(&recipes(), &[]).is_none());
+    }
+}
@@ -0,0 +1,398 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::{AppLooplessStateExt, ConditionSet};
+
+use crate::config::data_layout::LayoutData;
+use crate::game::auto_use::{cycle_auto_use, is_consumable, next_auto_use, AutoUsePolicy};
+use crate::game::backpack::{upgrade_backpack, Backpack, BackpackInUse};
+use crate::game::combat::Hero;
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::item_info_system::TooltipBg;
+use crate::game::recipe_book::find_crafting_space;
+use crate::game::{
+    find_free_space, remove_stat_bonus, stack_count, swap_equipment, take_from_stack, use_item,
+    CraftItem, EquippedItem, FontId, Item, ItemStack, SpawnItemEvent, StackItemEvent,
+};
+use crate::mouse::{Mouse, MouseInteractive};
+use crate::positioning::{Coords, GridData};
+use crate::states::AppState;
+
+pub struct ContextMenuPlugin;
+
+impl Plugin for ContextMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ContextMenu>()
+            .add_event::<ItemActionEvent>()
+            .add_enter_system(AppState::InGame, reset_context_menu)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(open_context_menu)
+                    .with_system(draw_context_menu)
+                    .with_system(apply_item_action)
+                    .into(),
+            );
+    }
+}
+
+/// Resource. The menu of things to do with an item that right-clicking it opens.
+#[derive(Default)]
+pub struct ContextMenu {
+    /// The item the menu is open for, if it is open at all.
+    pub target: Option<Entity>,
+    /// Where the menu is drawn, in egui points. Taken from the pointer once the menu opens.
+    position: Option<egui::Pos2>,
+    /// Whether the menu asks the player to confirm throwing the item away.
+    confirm_discard: bool,
+}
+
+/// Something the player can do with an item through its context menu.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ItemAction {
+    Use,
+    Equip,
+    MoveToCrafting,
+    MoveToInventory,
+    SplitStack,
+    AutoUse,
+    Discard,
+}
+
+/// Broadcast this to do something with an item, as if it was chosen from its context menu.
+pub struct ItemActionEvent {
+    pub item: Entity,
+    pub action: ItemAction,
+}
+
+/// Whether using the item would do anything: change a stat, apply a timed effect, or upgrade the
+/// backpack. Items whose data lists all-zero bonuses only get used up.
+fn has_use_effect(item: &Item) -> bool {
+    let stats = item.stat_bonuses.unwrap_or_default();
+    let stats_change = stats.health != 0
+        || stats.max_health != 0
+        || stats.proficiency != 0
+        || stats.damage_bonus != 0
+        || stats.damage_res != 0;
+    let effect_changes = item.temporary_effect.as_ref().map_or(false, |effect| {
+        effect.max_health_mod != 0
+            || effect.combat_prof_mod != 0
+            || effect.damage_mod != 0
+            || effect.damage_res_mod != 0
+    });
+    stats_change || effect_changes || item.backpack_upgrade.is_some()
+}
+
+/// The things that can be done with an item, going by what it is and where it is.
+pub fn item_actions(item: &Item, count: usize, crafting: bool, equipped: bool) -> Vec<ItemAction> {
+    if equipped {
+        return vec![ItemAction::MoveToInventory];
+    }
+    let mut actions = vec![];
+    if item.wearable.is_some() {
+        actions.push(ItemAction::Equip);
+    } else if has_use_effect(item) {
+        actions.push(ItemAction::Use);
+    }
+    if is_consumable(item) {
+        actions.push(ItemAction::AutoUse);
+    }
+    actions.push(if crafting {
+        ItemAction::MoveToInventory
+    } else {
+        ItemAction::MoveToCrafting
+    });
+    if count > 1 {
+        actions.push(ItemAction::SplitStack);
+    }
+    actions.push(ItemAction::Discard);
+    actions
+}
+
+fn reset_context_menu(mut menu: ResMut<ContextMenu>) {
+    *menu = ContextMenu::default();
+}
+
+/// The OpenContextMenu binding (a right-click by default) on an item opens its context menu. The
+/// mouse leaves the game alone while the menu is open, so clicking the menu doesn't also click the
+/// items under it.
+fn open_context_menu(
+    mut menu: ResMut<ContextMenu>,
+    mut mouse: ResMut<Mouse>,
+    items: Query<(Entity, &MouseInteractive), With<Item>>,
+) {
+    if mouse.is_dragging || menu.target.is_some() {
+        return;
+    }
+    if let Some((e, _)) = items
+        .iter()
+        .find(|(_, interactive)| interactive.right_clicked)
+    {
+        *menu = ContextMenu {
+            target: Some(e),
+            ..default()
+        };
+        mouse.disabled = true;
+    }
+}
+
+/// Draws the context menu next to the item it was opened for. Clicking anywhere else closes it.
+fn draw_context_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut menu: ResMut<ContextMenu>,
+    mut mouse: ResMut<Mouse>,
+    mut writer: EventWriter<ItemActionEvent>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<(
+        &Item,
+        Option<&Backpack>,
+        Option<&ItemStack>,
+        Option<&CraftItem>,
+        Option<&EquippedItem>,
+        Option<&AutoUsePolicy>,
+    )>,
+) {
+    let Some(target) = menu.target else {
+        return;
+    };
+    let Ok((item, backpack, stack, craft, equipped, policy)) = items.get(target) else {
+        // The item is gone, for instance used up or evolved away.
+        *menu = ContextMenu::default();
+        mouse.disabled = false;
+        return;
+    };
+    let era = backpack
+        .map(|backpack| backpack.0)
+        .or_else(|| backpack_in_use.get_single().ok().map(|in_use| in_use.0))
+        .unwrap_or_default();
+    let actions = item_actions(
+        item,
+        stack_count(stack),
+        craft.is_some(),
+        equipped.is_some(),
+    );
+
+    let ctx = egui_context.ctx_mut();
+    let just_opened = menu.position.is_none();
+    let position = *menu
+        .position
+        .get_or_insert_with(|| ctx.input().pointer.hover_pos().unwrap_or_default());
+    let mut chosen = None;
+    let area = egui::Area::new("item_context_menu")
+        .order(egui::Order::Foreground)
+        .fixed_pos(position)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_min_width(140.);
+                ui.label(egui::RichText::new(item.name_in(era)).strong());
+                ui.separator();
+                if menu.confirm_discard {
+                    ui.label("Throw it away for good?");
+                    ui.horizontal(|ui| {
+                        if ui.button("Discard").clicked() {
+                            chosen = Some(ItemAction::Discard);
+                        }
+                        if ui.button("Keep").clicked() {
+                            menu.confirm_discard = false;
+                        }
+                    });
+                    return;
+                }
+                for action in actions {
+                    let label = match action {
+                        ItemAction::Use => "Use".to_string(),
+                        ItemAction::Equip => "Equip".to_string(),
+                        ItemAction::MoveToCrafting => "Move to crafting area".to_string(),
+                        ItemAction::MoveToInventory => "Move to backpack".to_string(),
+                        ItemAction::SplitStack => "Split stack".to_string(),
+                        ItemAction::AutoUse => match next_auto_use(item, policy) {
+                            Some(next) => format!("Auto-use {}", next.describe()),
+                            None => "Stop auto-using".to_string(),
+                        },
+                        ItemAction::Discard => "Discard...".to_string(),
+                    };
+                    if ui.button(label).clicked() {
+                        if action == ItemAction::Discard {
+                            menu.confirm_discard = true;
+                        } else {
+                            chosen = Some(action);
+                        }
+                    }
+                }
+            });
+        });
+
+    let input = ctx.input();
+    let clicked_elsewhere = !just_opened
+        && input.pointer.any_pressed()
+        && !input
+            .pointer
+            .interact_pos()
+            .map_or(false, |pos| area.response.rect.contains(pos));
+    drop(input);
+    if let Some(action) = chosen {
+        writer.send(ItemActionEvent {
+            item: target,
+            action,
+        });
+    }
+    if chosen.is_some() || clicked_elsewhere {
+        *menu = ContextMenu::default();
+        mouse.disabled = false;
+    }
+}
+
+/// Carry out what the player chose from an item's context menu.
+fn apply_item_action(
+    mut commands: Commands,
+    mut hero: ResMut<Hero>,
+    mut grid: ResMut<GridData>,
+    layout: Res<LayoutData>,
+    mut events: EventReader<ItemActionEvent>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+    mut ew_spawn_item: EventWriter<SpawnItemEvent>,
+    mut ew_stack_item: EventWriter<StackItemEvent>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<(
+        Entity,
+        &Item,
+        &Coords,
+        Option<&Backpack>,
+        Option<&ItemStack>,
+        Option<&CraftItem>,
+        Option<&EquippedItem>,
+        Option<&AutoUsePolicy>,
+    )>,
+    transforms: Query<&Transform, With<Item>>,
+    equipped_items_query: Query<(Entity, &Item, &EquippedItem)>,
+    tooltips: Query<Entity, With<TooltipBg>>,
+) {
+    for ItemActionEvent { item: e, action } in events.iter() {
+        let Ok((e, item, coords, backpack, stack, craft, equipped, policy)) = items.get(*e) else {
+            continue;
+        };
+        let Some(backpack_id) = backpack
+            .map(|backpack| backpack.0)
+            .or_else(|| backpack_in_use.get_single().ok().map(|in_use| in_use.0))
+        else {
+            error!("There should be only one BackpackInUse component in game.");
+            continue;
+        };
+        // The spots taken in the part of the grid the item is in, its own included.
+        let taken = items
+            .iter()
+            .filter(|(_, _, _, other_backpack, _, other_craft, _, _)| {
+                other_backpack.map(|b| b.0) == Some(backpack_id)
+                    && other_craft.is_some() == craft.is_some()
+            })
+            .map(|(_, _, coords, _, _, _, _, _)| *coords)
+            .collect::<Vec<_>>();
+        let count = stack_count(stack);
+        let mut message = None;
+        match action {
+            ItemAction::Use => {
+                if let Some(upgrade) = item.backpack_upgrade {
+                    message = Some(upgrade_backpack(
+                        &mut commands,
+                        &mut grid,
+                        &layout,
+                        e,
+                        upgrade,
+                        stack,
+                        &tooltips,
+                    ));
+                } else {
+                    use_item(&mut commands, &mut hero, e, item, stack, &tooltips);
+                }
+            }
+            ItemAction::Equip => {
+                swap_equipment(
+                    &mut commands,
+                    &mut hero,
+                    &grid,
+                    e,
+                    item,
+                    (backpack_id, *coords),
+                    &equipped_items_query,
+                    &mut ew_stack_item,
+                );
+                for tooltip in tooltips.iter() {
+                    commands.entity(tooltip).despawn_recursive();
+                }
+            }
+            ItemAction::MoveToCrafting => {
+                let crafting_taken = items
+                    .iter()
+                    .filter(|(_, _, _, other_backpack, _, other_craft, _, _)| {
+                        other_backpack.map(|b| b.0) == Some(backpack_id) && other_craft.is_some()
+                    })
+                    .map(|(_, _, coords, _, _, _, _, _)| *coords)
+                    .collect::<Vec<_>>();
+                // The item is moved rather than spawned anew, so it keeps its rotation and its
+                // auto-use policy.
+                if let Some(spot) = find_crafting_space(&grid, coords, &crafting_taken) {
+                    let mut transform = transforms.get(e).copied().unwrap_or_default();
+                    transform.translation.x = grid.calc_x(&spot);
+                    transform.translation.y = grid.calc_y(&spot);
+                    commands
+                        .entity(e)
+                        .insert(spot)
+                        .insert(transform)
+                        .insert(CraftItem);
+                } else {
+                    message = Some(format!(
+                        "There is no room for the {} there.",
+                        item.name_in(backpack_id)
+                    ));
+                }
+            }
+            ItemAction::MoveToInventory => {
+                if equipped.is_some() {
+                    remove_stat_bonus(&mut hero, item);
+                }
+                commands.entity(e).despawn_recursive();
+                ew_stack_item.send(StackItemEvent {
+                    item: item.clone(),
+                    count,
+                    backpack: Some(backpack_id),
+                    source: None,
+                    vacated: None,
+                });
+            }
+            ItemAction::SplitStack => {
+                let half = count / 2;
+                let spot = if craft.is_some() {
+                    find_crafting_space(&grid, &item.footprint_at(coords.pos), &taken)
+                } else {
+                    find_free_space(&grid, item.dimens, item.shape, &taken, &[])
+                };
+                if let Some(spot) = spot {
+                    take_from_stack(&mut commands, e, stack, half);
+                    let mut evt = SpawnItemEvent::without_anim(item.clone(), spot).stacked(half);
+                    evt.backpack = Some(backpack_id);
+                    ew_spawn_item.send(evt);
+                } else {
+                    message = Some(format!(
+                        "There is no room to split the {}.",
+                        item.name_in(backpack_id)
+                    ));
+                }
+            }
+            ItemAction::AutoUse => {
+                cycle_auto_use(&mut commands, &mut feed, e, item, backpack_id, policy)
+            }
+            ItemAction::Discard => {
+                commands.entity(e).despawn_recursive();
+                for tooltip in tooltips.iter() {
+                    commands.entity(tooltip).despawn_recursive();
+                }
+            }
+        }
+        if let Some(message) = message {
+            feed.send(AddFeedItemEvent {
+                message,
+                colour: MessageColour::Neutral,
+                font: FontId::FiraSansItalic,
+            });
+        }
+    }
+}
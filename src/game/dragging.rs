@@ -1,13 +1,19 @@
 use bevy::prelude::*;
 
-use crate::game::items::{CraftItem, Item};
-use crate::game::{AssetStorage, CleanupOnGameplayEnd, Silhouette};
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::combat::Hero;
+use crate::game::items::{
+    stack_count, swap_equipment, take_from_stack, unequip_item, CraftItem, EquipmentSlot,
+    EquippedItem, Item, ItemRotation, ItemStack,
+};
+use crate::game::{CleanupOnGameplayEnd, Silhouette};
 use crate::mouse::{Mouse, MouseInteractive};
 use crate::positioning::Depth;
 use crate::positioning::Pos;
-use crate::positioning::{Coords, GridData};
+use crate::positioning::{Coords, Dimens, GridData};
 
-use super::StackItemEvent;
+use super::{SpawnItemEvent, StackItemEvent};
 
 /// Broadcast this event when completing a dragging operation.
 /// The entity that is being dragged still has the BeingDragged component.
@@ -27,6 +33,12 @@ pub struct DragGhost {
     /// Accounts for cases where the player didn't start the dragging on the bottom-left corner.
     cursor_delta: Pos,
     placement_valid: bool,
+    /// The rotation the item will have if it is dropped here.
+    rotation: ItemRotation,
+    /// If only part of a stack is being dragged off, how many items that part holds.
+    split: Option<usize>,
+    /// The equipment slot on the hero widget that the cursor is over, if any.
+    equip_slot: Option<EquipmentSlot>,
 }
 
 /// TODO: There's no logic separating normal clicks from drag initiation.
@@ -37,19 +49,44 @@ pub struct DragGhost {
 ///     - That item is marked with the BeingDragged component.
 ///     - A ghost item is spawned.
 ///     - The mouse is tagged as being in the middle of a dragging operation.
+/// Dragging a stack with LCtrl held splits off half of it.
+/// Items dragged out of an equipment slot get their full size back.
+/// Nothing is picked up while the ghost of the last drag is still around, so the click that drops
+/// an item doesn't pick up whatever it was dropped on.
 pub fn check_drag_begin(
     mut commands: Commands,
-    assets: Res<AssetStorage>,
     grid: Res<GridData>,
     mut mouse: ResMut<Mouse>,
-    query: Query<(&Coords, Entity, &Item, &MouseInteractive), Without<Silhouette>>,
+    query_ghost: Query<(), With<DragGhost>>,
+    query: Query<
+        (
+            &Coords,
+            Entity,
+            &Item,
+            &MouseInteractive,
+            Option<&ItemRotation>,
+            Option<&ItemStack>,
+            Option<&EquippedItem>,
+            &Handle<Image>,
+        ),
+        Without<Silhouette>,
+    >,
 ) {
-    if mouse.is_dragging {
+    if mouse.is_dragging || !query_ghost.is_empty() {
         return;
     }
-    for (coords, entity, item, interactive) in query.iter() {
+    for (coords, entity, item, interactive, rotation, stack, equipped, texture) in query.iter() {
         if interactive.clicked && !interactive.shift_clicked && !interactive.ctrl_alt_clicked {
             let hovered_over_cell = Pos::from(mouse.position - grid.offset);
+            let rotation = rotation.copied().unwrap_or_default();
+            // In its slot, the item is shrunk down to the size of the slot.
+            let coords = &if equipped.is_some() {
+                item.footprint_at(coords.pos)
+            } else {
+                *coords
+            };
+            let count = stack_count(stack);
+            let split = (interactive.ctrl_clicked && count > 1).then_some(count / 2);
             commands.entity(entity).insert(BeingDragged);
             commands.entity(entity).insert(Silhouette);
             commands
@@ -57,20 +94,25 @@ pub fn check_drag_begin(
                     sprite: Sprite {
                         color: Color::rgba(1., 1., 1., 0.5),
                         // BOGAY: this affects the placeholder follows the mouse cursor
-                        custom_size: Some(coords.dimens.as_vec2()),
+                        // The sprite keeps the unrotated size, the transform turns it.
+                        custom_size: Some(item.dimens.as_vec2()),
                         ..default()
                     },
-                    texture: assets.texture(&item.texture_id),
+                    // Same sprite as the item, which depends on the era it is in.
+                    texture: texture.clone(),
                     transform: Transform::from_xyz(
                         grid.calc_x(coords),
                         grid.calc_y(coords),
                         Depth::FloatingItem.z(),
-                    ),
+                    )
+                    .with_rotation(rotation.as_quat()),
                     ..Default::default()
                 })
                 .insert(*coords)
                 .insert(DragGhost {
                     cursor_delta: coords.pos - hovered_over_cell,
+                    rotation,
+                    split,
                     ..default()
                 })
                 .insert(CleanupOnGameplayEnd);
@@ -83,96 +125,335 @@ pub fn check_drag_begin(
 pub fn set_ghost_position(
     grid: Res<GridData>,
     mouse: Res<Mouse>,
-    mut query: Query<(&mut Transform, &mut Coords, &DragGhost)>,
+    mut query: Query<(&mut Transform, &mut Coords, &mut DragGhost)>,
 ) {
-    if let Ok((mut transform, mut coords, ghost)) = query.get_single_mut() {
-        coords.pos = Pos::from(mouse.position - grid.offset) + ghost.cursor_delta;
+    if let Ok((mut transform, mut coords, mut ghost)) = query.get_single_mut() {
+        let hovered_over_cell = Pos::from(mouse.position - grid.offset);
+        ghost.equip_slot = grid.equipment_slot_at(&hovered_over_cell);
+        coords.pos = hovered_over_cell + ghost.cursor_delta;
         transform.translation.x = grid.calc_x(&coords);
         transform.translation.y = grid.calc_y(&coords);
     }
 }
 
+/// Turn the ghost a quarter clockwise when the player presses the RotateItem binding (R, a
+/// right-click or the right bumper of a gamepad by default) mid-drag.
+/// The footprint is rotated along with the sprite, and the cursor is kept on top of the ghost.
+pub fn rotate_ghost(
+    grid: Res<GridData>,
+    mouse: Res<Mouse>,
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pad: Res<Input<GamepadButton>>,
+    mut query_ghost: Query<
+        (&mut DragGhost, &mut Sprite, &mut Transform, &mut Coords),
+        Without<BeingDragged>,
+    >,
+    query_items: Query<
+        (&Coords, &Item, &Backpack, Option<&ItemStack>),
+        (Without<BeingDragged>, Without<DragGhost>),
+    >,
+    being_dragged: Query<(&Item, &Coords, Option<&EquippedItem>), With<BeingDragged>>,
+    backpack_in_use: Query<&BackpackInUse>,
+) {
+    if !mouse.is_dragging || !config.just_pressed(InputAction::RotateItem, &keys, &buttons, &pad) {
+        return;
+    }
+    let Ok((mut ghost, mut sprite, mut transform, mut coords)) = query_ghost.get_single_mut()
+    else {
+        return;
+    };
+    let Ok(dragged) = being_dragged.get_single() else {
+        return;
+    };
+    let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+        return;
+    };
+    // Offset of the grabbed cell from the bottom-left corner, before and after the turn.
+    let grabbed = Pos::new(0, 0) - ghost.cursor_delta;
+    let rotated_grab = Pos::new(grabbed.y, coords.dimens.x - 1 - grabbed.x);
+    coords.mask = coords.mask.map(|mask| mask.rotated());
+    coords.dimens = Dimens::new(coords.dimens.y, coords.dimens.x);
+    ghost.cursor_delta = Pos::new(0, 0) - rotated_grab;
+    ghost.rotation = ghost.rotation.turned();
+
+    let hovered_over_cell = Pos::from(mouse.position - grid.offset);
+    coords.pos = hovered_over_cell + ghost.cursor_delta;
+    transform.translation.x = grid.calc_x(&coords);
+    transform.translation.y = grid.calc_y(&coords);
+    transform.rotation = ghost.rotation.as_quat();
+    update_ghost_validity(
+        &grid,
+        &mut ghost,
+        &mut sprite,
+        &coords,
+        dragged,
+        *backpack_id,
+        &query_items,
+    );
+}
+
 /// Checks if the dragging move would be valid. If not, tints the ghost red.
 pub fn check_ghost_placement_validity(
     grid: Res<GridData>,
-    mut query_ghost: Query<(&mut DragGhost, &mut Sprite, &Coords)>,
-    query_items: Query<(&Coords, &Item), Without<BeingDragged>>,
-    mut being_dragged: Query<&Item, With<BeingDragged>>,
+    mut query_ghost: Query<(&mut DragGhost, &mut Sprite, &Coords), Without<BeingDragged>>,
+    query_items: Query<
+        (&Coords, &Item, &Backpack, Option<&ItemStack>),
+        (Without<BeingDragged>, Without<DragGhost>),
+    >,
+    being_dragged: Query<(&Item, &Coords, Option<&EquippedItem>), With<BeingDragged>>,
+    backpack_in_use: Query<&BackpackInUse>,
 ) {
-    let is_inside_grid = |coords| grid.inventory.encloses(coords) || grid.crafting.encloses(coords);
     if let Ok((mut ghost, mut sprite, coords)) = query_ghost.get_single_mut() {
-        let Ok(dragged_item) = being_dragged.get_single_mut() else { 
+        let Ok(dragged) = being_dragged.get_single() else {
             error!("there should be only 1 BeingDragged");
             return;
         };
-        let conflicted_item = query_items
-            .iter()
-            .find_map(|(icoords, item)| (coords.overlaps(icoords) && dragged_item.id != item.id).then_some(item));
-        if is_inside_grid(coords) && !conflicted_item.is_some() {
-            ghost.placement_valid = true;
-            sprite.color = Color::rgba(1., 1., 1., 0.5);
+        let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+            error!("There should be only one BackpackInUse component in game.");
+            return;
+        };
+        update_ghost_validity(
+            &grid,
+            &mut ghost,
+            &mut sprite,
+            coords,
+            dragged,
+            *backpack_id,
+            &query_items,
+        );
+    }
+}
+
+/// The ghost can go anywhere in the grids that is either free, or taken up by nothing but a stack
+/// of the same item that still has room. Wearable items can also go in their own slot on the hero
+/// widget, even if something is in it already.
+fn update_ghost_validity(
+    grid: &GridData,
+    ghost: &mut DragGhost,
+    sprite: &mut Sprite,
+    coords: &Coords,
+    (dragged_item, dragged_coords, dragged_equipped): (&Item, &Coords, Option<&EquippedItem>),
+    backpack_id: usize,
+    query_items: &Query<
+        (&Coords, &Item, &Backpack, Option<&ItemStack>),
+        (Without<BeingDragged>, Without<DragGhost>),
+    >,
+) {
+    if let Some(slot) = ghost.equip_slot {
+        ghost.placement_valid = dragged_item.wearable == Some(slot) && ghost.split.is_none();
+        sprite.color = if ghost.placement_valid {
+            Color::rgba(1., 1., 1., 0.5)
         } else {
-            ghost.placement_valid = false;
-            sprite.color = Color::rgba(1., 0., 0., 0.5);
-        }
+            Color::rgba(1., 0., 0., 0.5)
+        };
+        return;
+    }
+    let is_inside_grid = |coords| grid.inventory.encloses(coords) || grid.crafting.encloses(coords);
+    // The other backpack's items share the grid, but aren't in the way.
+    let conflicts = query_items
+        .iter()
+        .filter(|(icoords, _, backpack, _)| backpack.0 == backpack_id && coords.overlaps(icoords))
+        .collect::<Vec<_>>();
+    let fits = match conflicts.as_slice() {
+        [] => true,
+        [(_, item, _, stack)] => item.id == dragged_item.id && stack_count(*stack) < item.max_stack,
+        _ => false,
+    };
+    // A part split off a stack can't be put down on top of the stack it came from.
+    let onto_itself =
+        ghost.split.is_some() && dragged_equipped.is_none() && coords.overlaps(dragged_coords);
+    if is_inside_grid(coords) && fits && !onto_itself {
+        ghost.placement_valid = true;
+        sprite.color = Color::rgba(1., 1., 1., 0.5);
+    } else {
+        ghost.placement_valid = false;
+        sprite.color = Color::rgba(1., 0., 0., 0.5);
     }
 }
 
-/// Check if the dragging operation should be concluded. If so;
+/// Check if the dragging operation should be concluded, either by letting go of the mouse button,
+/// by clicking again in click-to-drag mode, or by a click of the grid cursor. If so;
 /// - Delete the ghost entity.
 /// - Mark the mouse as no longer in the middle of a drag operation.
 /// - Broadcast a DragEvent.
 pub fn check_drag_end(
     mut writer: EventWriter<DragEvent>,
     mut mouse: ResMut<Mouse>,
+    config: Res<InputConfig>,
     input: Res<Input<MouseButton>>,
     query_ghost: Query<&Coords, With<DragGhost>>,
 ) {
-    if !mouse.is_dragging || !input.just_released(MouseButton::Left) {
+    let released = if config.click_to_drag {
+        input.just_pressed(MouseButton::Left)
+    } else {
+        input.just_released(MouseButton::Left)
+    };
+    if !mouse.is_dragging || !(released || mouse.cursor_actions.click) {
         return;
     }
+    // Click-to-drag and the grid cursor pick up and drop with the same click. The ghost only shows
+    // up the frame after the pick-up, which keeps that same click from dropping the item again.
+    let Ok(ghost_coords) = query_ghost.get_single() else {
+        return;
+    };
     mouse.is_dragging = false;
-    let ghost_coords = query_ghost.single();
     writer.send(DragEvent(ghost_coords.pos));
 }
 
+/// The CancelDrag binding (Escape or the east button of a gamepad by default) puts the dragged
+/// item back where it was. Runs before `handle_escape`, and takes the key press so it doesn't also
+/// leave the game.
+pub fn cancel_drag(
+    mut commands: Commands,
+    mut mouse: ResMut<Mouse>,
+    config: Res<InputConfig>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut buttons: ResMut<Input<MouseButton>>,
+    mut pad: ResMut<Input<GamepadButton>>,
+    query_ghost: Query<Entity, With<DragGhost>>,
+    being_dragged: Query<Entity, With<BeingDragged>>,
+) {
+    if !mouse.is_dragging {
+        return;
+    }
+    if !config.clear_just_pressed(InputAction::CancelDrag, &mut keys, &mut buttons, &mut pad) {
+        return;
+    }
+    mouse.is_dragging = false;
+    for ghost in query_ghost.iter() {
+        commands.entity(ghost).despawn_recursive();
+    }
+    for entity in being_dragged.iter() {
+        commands.entity(entity).remove::<BeingDragged>();
+        commands.entity(entity).remove::<Silhouette>();
+    }
+}
+
+/// Put the dragged item down where the ghost is. Dropping it onto a stack of the same item merges
+/// as much of it into that stack as fits, and whatever is left stays where it was. A part split
+/// off a stack becomes a stack of its own. Dropping a wearable item in its slot on the hero widget
+/// equips it, and whatever was in that slot goes back into the backpack. Dragging an equipped item
+/// back out into the grid takes it off again.
 pub fn process_drag_event(
     mut commands: Commands,
     grid: Res<GridData>,
+    mut hero: ResMut<Hero>,
     mut events: EventReader<DragEvent>,
-    query_ghost: Query<(Entity, &DragGhost)>,
-    mut query_item: Query<(Entity, &mut Transform, &mut Coords, &Item), With<BeingDragged>>,
-    query_other_items: Query<(&Coords, &Item), Without<BeingDragged>>,
+    query_ghost: Query<(Entity, &DragGhost, &Coords), Without<BeingDragged>>,
+    mut query_item: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Coords,
+            &mut MouseInteractive,
+            &Item,
+            Option<&Backpack>,
+            Option<&ItemStack>,
+            Option<&EquippedItem>,
+        ),
+        With<BeingDragged>,
+    >,
+    query_other_items: Query<
+        (Entity, &Coords, &Item, &Backpack, Option<&ItemStack>),
+        (Without<BeingDragged>, Without<DragGhost>),
+    >,
+    query_equipped: Query<(Entity, &Item, &EquippedItem)>,
+    backpack_in_use: Query<&BackpackInUse>,
+    mut ew_spawn_item: EventWriter<SpawnItemEvent>,
     mut ew_stack_item: EventWriter<StackItemEvent>,
 ) {
     for DragEvent(end) in events.iter() {
-        if let Ok((entity, mut transform, mut coords, dragged_item)) = query_item.get_single_mut() {
-            let (ghost_entity, ghost) = query_ghost.single();
+        if let Ok((
+            entity,
+            mut transform,
+            mut coords,
+            mut interactive,
+            dragged_item,
+            backpack,
+            stack,
+            equipped,
+        )) = query_item.get_single_mut()
+        {
+            let (ghost_entity, ghost, ghost_coords) = query_ghost.single();
             commands.entity(ghost_entity).despawn_recursive();
             commands.entity(entity).remove::<BeingDragged>();
             commands.entity(entity).remove::<Silhouette>();
-            if ghost.placement_valid {
-                coords.pos = *end;
-                transform.translation.x = grid.calc_x(&coords);
-                transform.translation.y = grid.calc_y(&coords);
-
-                if grid.crafting.encloses(&coords) {
-                    commands.entity(entity).insert(CraftItem);
-                } else if grid.inventory.encloses(&coords) {
-                    commands.entity(entity).remove::<CraftItem>();
-
-                    let stackable = query_other_items.iter().any(
-                    |(coords, item)| end == &coords.pos && dragged_item.id == item.id);
-                    if stackable {
-                        // remove item being dragged and stack it
-                        commands.entity(entity).despawn();
-                        ew_stack_item.send(StackItemEvent {
-                            item : dragged_item.clone(),
-                            count: 1,
-                            backpack: None,
-                        });
-                    }
+            if !ghost.placement_valid {
+                continue;
+            }
+            // Items taken off the hero go into the backpack that is on screen.
+            let Some(backpack_id) = backpack
+                .map(|backpack| backpack.0)
+                .or_else(|| backpack_in_use.get_single().ok().map(|in_use| in_use.0))
+            else {
+                error!("There should be only one BackpackInUse component in game.");
+                continue;
+            };
+            if ghost.equip_slot.is_some() {
+                // Dropping an item back in the slot it came from changes nothing.
+                if equipped.is_none() {
+                    swap_equipment(
+                        &mut commands,
+                        &mut hero,
+                        &grid,
+                        entity,
+                        dragged_item,
+                        (backpack_id, *coords),
+                        &query_equipped,
+                        &mut ew_stack_item,
+                    );
                 }
+                continue;
+            }
+            if equipped.is_some() {
+                unequip_item(&mut commands, &mut hero, entity, dragged_item, backpack_id);
+                transform.scale = Vec3::ONE;
+            }
+            let count = ghost.split.unwrap_or_else(|| stack_count(stack));
+
+            let merge_target = query_other_items
+                .iter()
+                .find(|(_, icoords, item, ibackpack, _)| {
+                    ibackpack.0 == backpack_id
+                        && ghost_coords.overlaps(icoords)
+                        && item.id == dragged_item.id
+                });
+            if let Some((target, _, item, _, target_stack)) = merge_target {
+                let target_count = stack_count(target_stack);
+                let moved = count.min(item.max_stack.saturating_sub(target_count));
+                commands
+                    .entity(target)
+                    .insert(ItemStack(target_count + moved));
+                take_from_stack(&mut commands, entity, stack, moved);
+                continue;
+            }
+
+            if let Some(split) = ghost.split {
+                take_from_stack(&mut commands, entity, stack, split);
+                let mut evt = SpawnItemEvent::without_anim(dragged_item.clone(), *ghost_coords)
+                    .stacked(split);
+                evt.backpack = Some(backpack_id);
+                evt.rotation = ghost.rotation;
+                ew_spawn_item.send(evt);
+                continue;
+            }
+
+            coords.pos = *end;
+            // Keep whatever rotation the ghost was given during the drag.
+            coords.dimens = ghost_coords.dimens;
+            coords.mask = ghost_coords.mask;
+            interactive.size = coords.dimens.as_vec2();
+            transform.rotation = ghost.rotation.as_quat();
+            commands.entity(entity).insert(ghost.rotation);
+            transform.translation.x = grid.calc_x(&coords);
+            transform.translation.y = grid.calc_y(&coords);
+
+            if grid.crafting.encloses(&coords) {
+                commands.entity(entity).insert(CraftItem);
+            } else if grid.inventory.encloses(&coords) {
+                commands.entity(entity).remove::<CraftItem>();
             }
         }
     }
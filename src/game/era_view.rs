@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::ConditionSet;
+
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::config::data_layout::LayoutData;
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::dungeon_gen::{TIMEPOINT_ANCIENT, TIMEPOINT_NOW};
+use crate::game::{stack_count, AssetStorage, Item, ItemRotation, ItemStack, LastEvolution};
+use crate::positioning::{Coords, Dimens, GridData};
+use crate::states::AppState;
+
+/// The backpacks of both eras, oldest first.
+const ERAS: [(i32, &str); 2] = [(TIMEPOINT_ANCIENT, "Ancient"), (TIMEPOINT_NOW, "Modern")];
+
+pub struct EraViewPlugin;
+
+impl Plugin for EraViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EraView>().add_system_set(
+            ConditionSet::new()
+                .run_in_state(AppState::InGame)
+                .with_system(toggle_era_view)
+                .with_system(draw_era_view_window)
+                .into(),
+        );
+    }
+}
+
+/// Resource. Whether the backpacks of both eras are shown side by side.
+#[derive(Default)]
+pub struct EraView {
+    pub show_window: bool,
+}
+
+/// The OpenEraView binding (`O` by default) opens and closes the side-by-side view of both eras.
+fn toggle_era_view(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut era_view: ResMut<EraView>,
+) {
+    if config.just_pressed(InputAction::OpenEraView, &keys, &buttons, &pads) {
+        era_view.show_window = !era_view.show_window;
+    }
+}
+
+/// Draws the backpack grids of both eras next to each other. Only the backpack in use can be
+/// changed, so the other one is dimmed. Items that the last evolution brought more of are
+/// outlined, and the changes are listed under the backpack that evolved.
+fn draw_era_view_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut era_view: ResMut<EraView>,
+    assets: Res<AssetStorage>,
+    layout: Res<LayoutData>,
+    grid: Res<GridData>,
+    last_evolution: Res<LastEvolution>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<(&Item, &Backpack, &Coords, &ItemRotation, Option<&ItemStack>)>,
+) {
+    if !era_view.show_window {
+        return;
+    }
+    let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+        error!("There should be only one BackpackInUse component in game.");
+        return;
+    };
+    let mut textures = HashMap::new();
+    for (item, backpack, _, _, _) in items.iter() {
+        let texture_id = item.texture_in(backpack.0);
+        textures
+            .entry(texture_id)
+            .or_insert_with(|| egui_context.add_image(assets.texture(&texture_id)));
+    }
+    let cell = layout.c_mid.era_view_cell;
+    let inventory = grid.inventory;
+
+    egui::Window::new("Both eras")
+        .open(&mut era_view.show_window)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.horizontal_top(|ui| {
+                for (timepoint, era) in ERAS {
+                    let backpack = timepoint as usize;
+                    let in_use = backpack == *backpack_id;
+                    ui.vertical(|ui| {
+                        ui.label(if in_use {
+                            format!("{} (in use)", era)
+                        } else {
+                            era.to_string()
+                        });
+                        let size = inventory.dimens.as_vec2() * cell;
+                        let (area, _) = ui
+                            .allocate_exact_size(egui::vec2(size.x, size.y), egui::Sense::hover());
+                        let painter = ui.painter_at(area);
+                        // The grid counts rows from the bottom, egui from the top.
+                        let cell_rect = |coords: &Coords| {
+                            let x = (coords.pos.x - inventory.pos.x) as f32 * cell;
+                            let y = (inventory.pos.y + inventory.dimens.y
+                                - coords.pos.y
+                                - coords.dimens.y) as f32
+                                * cell;
+                            egui::Rect::from_min_size(
+                                area.min + egui::vec2(x, y),
+                                egui::vec2(coords.dimens.x as f32, coords.dimens.y as f32) * cell,
+                            )
+                        };
+                        painter.rect_filled(area, 0., egui::Color32::from_gray(20));
+                        for y in 0..inventory.dimens.y {
+                            for x in 0..inventory.dimens.x {
+                                let coords =
+                                    Coords::new(inventory.pos.plus_xy(x, y), Dimens::unit());
+                                painter.rect_stroke(
+                                    cell_rect(&coords).shrink(1.),
+                                    2.,
+                                    egui::Stroke::new(1., egui::Color32::from_gray(60)),
+                                );
+                            }
+                        }
+                        let tint = if in_use {
+                            egui::Color32::WHITE
+                        } else {
+                            egui::Color32::from_gray(110)
+                        };
+                        for (item, _, coords, rotation, stack) in
+                            items.iter().filter(|(_, b, coords, _, _)| {
+                                b.0 == backpack && inventory.encloses(coords)
+                            })
+                        {
+                            let rect = cell_rect(coords);
+                            if let Some(texture) = textures.get(&item.texture_in(backpack)) {
+                                painter.add(rotated_image(*texture, rect, *rotation, tint));
+                            }
+                            let count = stack_count(stack);
+                            if count > 1 {
+                                painter.text(
+                                    rect.right_bottom() - egui::vec2(3., 2.),
+                                    egui::Align2::RIGHT_BOTTOM,
+                                    count.to_string(),
+                                    egui::FontId::proportional(12.),
+                                    egui::Color32::WHITE,
+                                );
+                            }
+                            if last_evolution.gained(backpack, &item.id) {
+                                painter.rect_stroke(
+                                    rect.shrink(1.),
+                                    2.,
+                                    egui::Stroke::new(2., egui::Color32::from_rgb(90, 220, 90)),
+                                );
+                            }
+                        }
+                        if last_evolution.backpack == Some(backpack) {
+                            ui.label("Last evolution:");
+                            for (item, before, after) in last_evolution.changes.iter() {
+                                let colour = if after > before {
+                                    egui::Color32::from_rgb(90, 220, 90)
+                                } else {
+                                    egui::Color32::from_rgb(220, 110, 90)
+                                };
+                                ui.colored_label(
+                                    colour,
+                                    format!("{}: {} -> {}", item.name_in(backpack), before, after),
+                                );
+                            }
+                        }
+                    });
+                }
+            });
+        });
+}
+
+/// An item's sprite stretched over its footprint, turned the way the player rotated it.
+fn rotated_image(
+    texture: egui::TextureId,
+    rect: egui::Rect,
+    rotation: ItemRotation,
+    tint: egui::Color32,
+) -> egui::Shape {
+    // Going round the corners clockwise, each quarter turn moves the texture one corner on.
+    let corners = [
+        rect.left_top(),
+        rect.right_top(),
+        rect.right_bottom(),
+        rect.left_bottom(),
+    ];
+    let uvs = [
+        egui::pos2(0., 0.),
+        egui::pos2(1., 0.),
+        egui::pos2(1., 1.),
+        egui::pos2(0., 1.),
+    ];
+    let turns = rotation.0 as usize % 4;
+    let mut mesh = egui::Mesh::with_texture(texture);
+    for (idx, pos) in corners.into_iter().enumerate() {
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos,
+            uv: uvs[(idx + 4 - turns) % 4],
+            color: tint,
+        });
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(0, 2, 3);
+    egui::Shape::mesh(mesh)
+}
@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::{AppLooplessStateExt, ConditionSet};
+
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::game::{CleanupOnGameplayEnd, CombineButton, Item};
+use crate::mouse::{Mouse, MouseInteractive, CURSOR_STAGE};
+use crate::positioning::{Depth, GridData, Pos};
+use crate::states::AppState;
+
+/// How far the cursor looks ahead for the next cell it can stop on, for example to hop over the
+/// gap between the backpack and the crafting grid.
+const MAX_HOP: i32 = 4;
+
+pub struct GridCursorPlugin;
+
+impl Plugin for GridCursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GridCursor>()
+            .add_enter_system(AppState::InGame, create_grid_cursor)
+            .add_system_set_to_stage(
+                CURSOR_STAGE,
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(move_grid_cursor)
+                    .into(),
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(show_grid_cursor)
+                    .into(),
+            );
+    }
+}
+
+/// Resource. A cursor that steps over the cells of the grids and the buttons with the keyboard or
+/// a gamepad, so the game can be played without a mouse. While it is in use, it moves the mouse
+/// position to the cell it is on and clicks on the mouse's behalf. Moving the real mouse hands
+/// control back to it.
+#[derive(Default)]
+pub struct GridCursor {
+    pub active: bool,
+    /// The cell the cursor is on, in the same coordinates as the items' Coords.
+    pub pos: Pos,
+    /// Where the real mouse was the last time it was looked at, to tell when it moves.
+    last_screen_position: Vec2,
+}
+
+/// Marker component for the frame drawn around the cell that the grid cursor is on.
+#[derive(Component)]
+pub struct GridCursorFrame;
+
+fn create_grid_cursor(mut commands: Commands, mut cursor: ResMut<GridCursor>) {
+    cursor.active = false;
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(1., 0.9, 0.3, 0.35),
+                custom_size: Some(Vec2::ONE),
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            transform: Transform::from_xyz(0., 0., Depth::Cursor.z()),
+            ..default()
+        })
+        .insert(Name::new("GridCursor"))
+        .insert(GridCursorFrame)
+        .insert(CleanupOnGameplayEnd);
+}
+
+/// The Cursor bindings move the cursor, pick up and drop, use, delete, and jump through time with
+/// the combine button. By default those are the arrow keys, WASD or the d-pad to move, and Enter,
+/// E, Delete and Tab, or the south, west, select and north buttons of a gamepad, for the rest.
+fn move_grid_cursor(
+    mut cursor: ResMut<GridCursor>,
+    mut mouse: ResMut<Mouse>,
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    pad: Res<Input<GamepadButton>>,
+    buttons: Res<Input<MouseButton>>,
+    grid: Res<GridData>,
+    clickables: Query<(&MouseInteractive, &GlobalTransform), Without<Item>>,
+    combine_button: Query<&GlobalTransform, With<CombineButton>>,
+) {
+    // Any use of the real mouse takes over from the cursor.
+    if mouse.screen_position != cursor.last_screen_position
+        || buttons.get_just_pressed().next().is_some()
+    {
+        cursor.last_screen_position = mouse.screen_position;
+        cursor.active = false;
+        return;
+    }
+
+    let pressed = |action| config.just_pressed(action, &keys, &buttons, &pad);
+    let step = [
+        (InputAction::CursorUp, Pos::new(0, 1)),
+        (InputAction::CursorDown, Pos::new(0, -1)),
+        (InputAction::CursorLeft, Pos::new(-1, 0)),
+        (InputAction::CursorRight, Pos::new(1, 0)),
+    ]
+    .into_iter()
+    .find(|(action, _)| pressed(*action))
+    .map(|(_, step)| step);
+    let click = pressed(InputAction::CursorClick);
+    let use_item = pressed(InputAction::CursorUse);
+    let delete = pressed(InputAction::CursorDelete);
+    let jump = pressed(InputAction::CursorJump);
+    if step.is_none() && !click && !use_item && !delete && !jump {
+        if cursor.active {
+            mouse.position = grid.offset + cursor.pos.as_vec2() + Vec2::splat(0.5);
+        }
+        return;
+    }
+
+    // A cell the cursor can stop on: one in a grid or an equipment slot, or one under a button.
+    let can_stop_on = |pos: Pos| {
+        let cell = grid.offset + pos.as_vec2() + Vec2::splat(0.5);
+        grid.inventory.overlaps_pos(&pos)
+            || grid.crafting.overlaps_pos(&pos)
+            || grid.equipment_slot_at(&pos).is_some()
+            || clickables.iter().any(|(interactive, transform)| {
+                let center = transform.translation().truncate();
+                interactive.clickable && (cell - center).abs().cmplt(interactive.size * 0.5).all()
+            })
+    };
+    if !cursor.active {
+        // Start where the mouse is if that is a sensible spot, or else in the top-left corner of
+        // the backpack.
+        let under_mouse = Pos::from(mouse.position - grid.offset);
+        cursor.pos = if can_stop_on(under_mouse) {
+            under_mouse
+        } else {
+            grid.inventory.pos.plus_y(grid.inventory.dimens.y - 1)
+        };
+        cursor.active = true;
+    } else if let Some(step) = step {
+        let mut next = cursor.pos;
+        for _ in 0..MAX_HOP {
+            next = next + step;
+            if can_stop_on(next) {
+                cursor.pos = next;
+                break;
+            }
+        }
+    }
+
+    mouse.position = grid.offset + cursor.pos.as_vec2() + Vec2::splat(0.5);
+    if jump {
+        if let Ok(transform) = combine_button.get_single() {
+            mouse.position = transform.translation().truncate();
+            cursor.pos = Pos::from(mouse.position - grid.offset);
+        }
+    }
+    mouse.cursor_actions.click = click || jump;
+    mouse.cursor_actions.use_item = use_item;
+    mouse.cursor_actions.delete = delete;
+}
+
+/// Draw the frame around the cell that the cursor is on, while the cursor is in use.
+fn show_grid_cursor(
+    cursor: Res<GridCursor>,
+    grid: Res<GridData>,
+    mut frame: Query<(&mut Transform, &mut Visibility), With<GridCursorFrame>>,
+) {
+    for (mut transform, mut visibility) in frame.iter_mut() {
+        visibility.is_visible = cursor.active;
+        let center = grid.offset + cursor.pos.as_vec2() + Vec2::splat(0.5);
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
@@ -1,7 +1,5 @@
-use crate::game::create_widget_hero::{
-    HeroCurrentArmourDisplay, HeroCurrentShieldDisplay, HeroCurrentWeaponDisplay,
-};
-use crate::game::{EquipmentSlot, EquippedItem};
+use crate::game::create_widget_hero::DungeonDepthDisplay;
+use crate::game::dungeon_sim::DungeonState;
 use bevy::prelude::*;
 
 use super::{
@@ -31,11 +29,9 @@ pub fn update_hero_stats_display(
         Query<&mut Text, With<HeroDamageResDisplay>>,
         Query<&mut Text, With<HeroDamageBonusDisplay>>,
         Query<&mut Text, With<HeroCurrentHealthDisplay>>,
-        Query<&mut Text, With<HeroCurrentArmourDisplay>>,
-        Query<&mut Text, With<HeroCurrentShieldDisplay>>,
-        Query<&mut Text, With<HeroCurrentWeaponDisplay>>,
+        Query<&mut Text, With<DungeonDepthDisplay>>,
     )>,
-    equipped_items_query: Query<&EquippedItem>,
+    state: Res<DungeonState>,
 ) {
     if let Ok(mut text) = query.p0().get_single_mut() {
         text.sections[0].value = format!("Combat Proficiency: {}", hero.combat_stats.proficiency);
@@ -53,24 +49,6 @@ pub fn update_hero_stats_display(
         );
     }
     if let Ok(mut text) = query.p4().get_single_mut() {
-        for equipped_item in equipped_items_query.iter() {
-            if equipped_item.slot == EquipmentSlot::Armour {
-                text.sections[0].value = format!("Armour: {}", equipped_item.name);
-            }
-        }
-    }
-    if let Ok(mut text) = query.p5().get_single_mut() {
-        for equipped_item in equipped_items_query.iter() {
-            if equipped_item.slot == EquipmentSlot::Shield {
-                text.sections[0].value = format!("Shield: {}", equipped_item.name);
-            }
-        }
-    }
-    if let Ok(mut text) = query.p6().get_single_mut() {
-        for equipped_item in equipped_items_query.iter() {
-            if equipped_item.slot == EquipmentSlot::Weapon {
-                text.sections[0].value = format!("Weapon: {}", equipped_item.name);
-            }
-        }
+        text.sections[0].value = format!("Depth: {}/{}", state.depth + 1, state.max_depth + 1);
     }
 }
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 
 use crate::config::data_layout::LayoutData;
-use crate::game::backpack::BackpackInUse;
+use crate::game::backpack::{BackpackInUse, BackpackOverflow};
 use crate::game::{AssetStorage, CleanupOnGameplayEnd, TextureId, MENU_ZOOM};
 use crate::main_menu::MenuBackpack;
 use crate::mouse::MouseInteractive;
@@ -65,4 +65,5 @@ pub fn create_layout_background(
 pub fn create_backpack_data(mut commands: Commands) {
     const INIT_BACKPACK_ID: usize = 0;
     commands.spawn().insert(BackpackInUse(INIT_BACKPACK_ID));
+    commands.insert_resource(BackpackOverflow::default());
 }
@@ -2,8 +2,10 @@ use bevy::prelude::*;
 use bevy::text::Text2dBounds;
 
 use crate::config::data_layout::LayoutData;
+use crate::game::recipe_book::RecipeBookButton;
+use crate::game::sort_backpack::SortButton;
 use crate::game::{
-    AssetStorage, CleanupOnGameplayEnd, CombineButton, Eyes, FontId, Iris, TextureId,
+    AssetStorage, CleanupOnGameplayEnd, CombineButton, CraftButton, Eyes, FontId, Iris, TextureId,
 };
 use crate::mouse::MouseInteractive;
 use crate::positioning::{Coords, Depth, Dimens, GridData, Pos};
@@ -17,13 +19,14 @@ pub fn create_layout_grids(
     let inventory_y = layout.c_mid.toasts.margin_bottom.unwrap_or(0.)
         + layout.c_mid.toasts.height.unwrap()
         + layout.c_mid.inventory.margin_bottom.unwrap_or(0.);
-    let inventory_coords = Coords::new(Pos::new(0, 0), Dimens::new(8, 5));
-    create_grid(
+    let inventory_coords = Coords::new(Pos::new(0, 0), layout.c_mid.inventory_grid);
+    let inventory_grid = create_grid(
         &mut commands,
         &assets,
         &inventory_coords.dimens,
         Vec2::new(inventory_x, inventory_y),
     );
+    commands.entity(inventory_grid).insert(InventoryGrid);
     /*
     let overseer_width = layout.middle_width();
     let overseer_height = overseer_width * 0.3; // Image is 1000x300.
@@ -85,7 +88,7 @@ pub fn create_layout_grids(
     */
     let x_crafting = layout.right_x() + 0.3333;
     let y_crafting = layout.c_right.crafting_y();
-    let crafting_coords = Coords::new(Pos::new(9, 1), Dimens::new(4, 3));
+    let crafting_coords = layout.c_right.crafting_grid;
     create_grid(
         &mut commands,
         &assets,
@@ -93,13 +96,37 @@ pub fn create_layout_grids(
         Vec2::new(x_crafting, y_crafting),
     );
 
+    let equipment = layout.c_right.equipment_grid.slot_coords();
+    for (slot, coords) in equipment.iter() {
+        let slot_grid = create_grid(
+            &mut commands,
+            &assets,
+            &coords.dimens,
+            Vec2::new(inventory_x, inventory_y) + coords.pos.as_vec2(),
+        );
+        // The slots sit on top of the hero widget, which is drawn at grid depth as well.
+        commands
+            .entity(slot_grid)
+            .insert(Name::new(format!("{:?} Slot", slot)))
+            .insert(Transform::from_xyz(
+                inventory_x + coords.pos.x as f32 + coords.dimens.x as f32 * 0.5,
+                inventory_y + coords.pos.y as f32 + coords.dimens.y as f32 * 0.5,
+                Depth::Grid.z() + 20.,
+            ));
+    }
+
     commands.insert_resource(GridData {
         offset: Vec2::new(inventory_x, inventory_y),
         inventory: inventory_coords,
         crafting: crafting_coords,
+        equipment,
     });
 }
 
+/// Marker component for the sprite of the backpack grid, which is redrawn when it grows.
+#[derive(Component)]
+pub struct InventoryGrid;
+
 /// Sets up the lower-right container.
 #[derive(Component)]
 pub struct CombineButtonText;
@@ -127,10 +154,7 @@ pub fn create_layout_combine_button(
         })
         .insert(Name::new("Combine Button"))
         .insert(CombineButton {
-            coords: Coords {
-                pos: Pos::new(18, 8),
-                dimens: Dimens::new(14, 4),
-            },
+            coords: Coords::new(Pos::new(18, 8), Dimens::new(14, 4)),
         })
         .insert(MouseInteractive::new(Vec2::new(width, height), true))
         .insert(CleanupOnGameplayEnd)
@@ -173,7 +197,123 @@ pub fn create_layout_combine_button(
         });
 }
 
-fn create_grid(commands: &mut Commands, assets: &AssetStorage, dimens: &Dimens, offset: Vec2) {
+const GRID_BUTTON_SIZE: Vec2 = Vec2::new(1.3333, 0.5);
+
+/// Where a small button goes above the top-right corner of a grid of the given offset and size.
+pub fn grid_button_position(grid_offset: Vec2, grid_dimens: Vec2) -> (f32, f32) {
+    (
+        grid_offset.x + grid_dimens.x - GRID_BUTTON_SIZE.x * 0.5,
+        grid_offset.y + grid_dimens.y + 0.1667 + GRID_BUTTON_SIZE.y * 0.5,
+    )
+}
+
+/// A small button just above the top-right corner of the inventory, to sort the backpack.
+pub fn create_layout_sort_button(
+    mut commands: Commands,
+    layout: Res<LayoutData>,
+    assets: Res<AssetStorage>,
+) {
+    // Same as the inventory in create_layout_grids.
+    let inventory_y = layout.c_mid.toasts.margin_bottom.unwrap_or(0.)
+        + layout.c_mid.toasts.height.unwrap()
+        + layout.c_mid.inventory.margin_bottom.unwrap_or(0.);
+    let inventory_dimens = layout.c_mid.inventory_grid.as_vec2();
+    let position =
+        grid_button_position(Vec2::new(layout.middle_x(), inventory_y), inventory_dimens);
+    let button = create_grid_button(&mut commands, &layout, &assets, position, "整理");
+    commands
+        .entity(button)
+        .insert(Name::new("Sort Button"))
+        .insert(SortButton);
+}
+
+/// A small button just above the top-right corner of the crafting grid, to craft whatever recipe
+/// the items in it make up.
+pub fn create_layout_craft_button(
+    mut commands: Commands,
+    layout: Res<LayoutData>,
+    assets: Res<AssetStorage>,
+) {
+    // Same as the crafting grid in create_layout_grids.
+    let crafting_offset = Vec2::new(layout.right_x() + 0.3333, layout.c_right.crafting_y());
+    let crafting_dimens = layout.c_right.crafting_grid.dimens.as_vec2();
+    let position = grid_button_position(crafting_offset, crafting_dimens);
+    let button = create_grid_button(&mut commands, &layout, &assets, position, "合成");
+    commands
+        .entity(button)
+        .insert(Name::new("Craft Button"))
+        .insert(CraftButton);
+}
+
+/// A small button left of the craft button, to open the recipe book.
+pub fn create_layout_recipe_book_button(
+    mut commands: Commands,
+    layout: Res<LayoutData>,
+    assets: Res<AssetStorage>,
+) {
+    // Same as the crafting grid in create_layout_grids.
+    let crafting_offset = Vec2::new(layout.right_x() + 0.3333, layout.c_right.crafting_y());
+    let crafting_dimens = layout.c_right.crafting_grid.dimens.as_vec2();
+    let (x, y) = grid_button_position(crafting_offset, crafting_dimens);
+    let position = (x - GRID_BUTTON_SIZE.x - 0.1667, y);
+    let button = create_grid_button(&mut commands, &layout, &assets, position, "配方");
+    commands
+        .entity(button)
+        .insert(Name::new("Recipe Book Button"))
+        .insert(RecipeBookButton);
+}
+
+fn create_grid_button(
+    commands: &mut Commands,
+    layout: &LayoutData,
+    assets: &AssetStorage,
+    (x, y): (f32, f32),
+    label: &str,
+) -> Entity {
+    let size = GRID_BUTTON_SIZE;
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.2, 0.2, 0.2, 0.8),
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_xyz(x, y, Depth::Grid.z()),
+            ..default()
+        })
+        .insert(MouseInteractive::new(size, true))
+        .insert(CleanupOnGameplayEnd)
+        .with_children(|parent| {
+            parent.spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: assets.font(&FontId::MSBold),
+                        font_size: 40.0,
+                        color: Color::ANTIQUE_WHITE,
+                    },
+                )
+                .with_alignment(TextAlignment {
+                    horizontal: HorizontalAlign::Center,
+                    vertical: VerticalAlign::Center,
+                }),
+                transform: Transform::from_xyz(0., 0., 1.).with_scale(Vec3::new(
+                    1. / layout.text_factor,
+                    1. / layout.text_factor,
+                    1.,
+                )),
+                ..default()
+            });
+        })
+        .id()
+}
+
+pub fn create_grid(
+    commands: &mut Commands,
+    assets: &AssetStorage,
+    dimens: &Dimens,
+    offset: Vec2,
+) -> Entity {
     commands
         .spawn_bundle(SpriteBundle {
             sprite: Sprite {
@@ -210,5 +350,6 @@ fn create_grid(commands: &mut Commands, assets: &AssetStorage, dimens: &Dimens,
                     });
                 }
             }
-        });
+        })
+        .id()
 }
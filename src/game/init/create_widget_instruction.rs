@@ -45,7 +45,7 @@ pub fn create_layout_instruction(
             let instruction_content = [
                 "- 按住滑鼠左鍵可拖曳道具",
                 "- 拖曳到右側的道具可以帶著穿越時空",
-                "- 按住 L-Ctrl + L-Alt 後點擊左鍵可刪除道具",
+                "- 右鍵點擊道具可開啟選單：使用、裝備、移動或丟棄",
             ]
             .join("\n");
             debug!("Instruction content: {:?}", instruction_content);
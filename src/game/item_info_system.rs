@@ -1,12 +1,24 @@
 use bevy::prelude::*;
 use bevy::text::{Text2dBounds, Text2dSize};
 
+use crate::config::data_items::ItemsData;
 use crate::config::data_layout::LayoutData;
-use crate::game::{AssetStorage, EquipmentSlot, FontId, Item, TextureId};
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::{
+    AssetStorage, EquipmentSlot, EquippedItem, FontId, Item, ItemId, StatBonus, TextureId,
+    EVOLUTION_RULES, POPULATION_SOURCES,
+};
 use crate::mouse::MouseInteractive;
 use crate::positioning::Depth;
 use crate::Mouse;
 
+/// How tall the stats part of the tooltip may get, in grid cells.
+const STATS_MAX_HEIGHT: f32 = 10.;
+/// Colour of a change that is good for the hero.
+const GOOD: Color = Color::rgb(0.55, 0.9, 0.5);
+/// Colour of a change that is bad for the hero.
+const BAD: Color = Color::rgb(0.95, 0.45, 0.4);
+
 /// === Components ===
 
 /// This marker component is added to entities with Item Components that are currently being moused over.
@@ -35,16 +47,40 @@ pub struct TooltipStats;
 /// Also deletes the tooltip if an item is not hovered over anymore.
 pub fn update_mouse_over_item_info_system(
     mut commands: Commands,
-    new_mouse_over_items_query: Query<(Entity, &MouseInteractive, &Item), Without<MousedOver>>,
+    new_mouse_over_items_query: Query<
+        (
+            Entity,
+            &MouseInteractive,
+            &Item,
+            Option<&Backpack>,
+            Option<&EquippedItem>,
+        ),
+        Without<MousedOver>,
+    >,
     old_mouse_over_items_query: Query<(Entity, &MouseInteractive, &Item), With<MousedOver>>,
+    equipped_items: Query<(&Item, &EquippedItem)>,
     item_info_query: Query<Entity, With<TooltipBg>>,
     assets: Res<AssetStorage>,
+    items_data: Res<ItemsData>,
     mouse: Res<Mouse>,
     layout: Res<LayoutData>,
+    backpack_in_use: Query<&BackpackInUse>,
 ) {
     // Add new item info
-    for (item_entity, mouse_interaction, item) in new_mouse_over_items_query.iter() {
+    for (item_entity, mouse_interaction, item, backpack, equipped) in
+        new_mouse_over_items_query.iter()
+    {
         if mouse_interaction.hovered {
+            // Equipped items are in no backpack, they look the way they do in the era in use.
+            let era = backpack
+                .map(|Backpack(id)| *id)
+                .or_else(|| {
+                    backpack_in_use
+                        .get_single()
+                        .ok()
+                        .map(|BackpackInUse(id)| *id)
+                })
+                .unwrap_or_default();
             commands.entity(item_entity).insert(MousedOver);
 
             // Spawn the container with the sprite background:
@@ -75,7 +111,7 @@ pub fn update_mouse_over_item_info_system(
                         .spawn_bundle(Text2dBundle {
                             // Default text, will probably never be seen:
                             text: Text::from_section(
-                                &item.name,
+                                item.name_in(era),
                                 TextStyle {
                                     font: assets.font(&FontId::MSBold),
                                     font_size: 80.0,
@@ -99,12 +135,23 @@ pub fn update_mouse_over_item_info_system(
                             ..default()
                         })
                         .insert(TooltipName);
-                    // Spawn the description text:
+                    // Spawn the description text, with the tags underneath:
+                    let description = if item.tags.is_empty() {
+                        item.description_in(era).to_string()
+                    } else {
+                        let tags = item
+                            .tags
+                            .iter()
+                            .map(|tag| tag.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        format!("{}\n[{}]", item.description_in(era), tags)
+                    };
                     parent
                         .spawn_bundle(Text2dBundle {
                             // Default text, will probably never be seen:
                             text: Text::from_section(
-                                &item.description,
+                                &description,
                                 TextStyle {
                                     font: assets.font(&FontId::MSBold),
                                     font_size: 60.0,
@@ -164,65 +211,43 @@ pub fn update_mouse_over_item_info_system(
                             })
                             .insert(TooltipWearable);
                     }
-                    // If applicable, spawn Stat Bonuses text:
-                    let stats_text = item
-                        .stat_bonuses
-                        .map(|stat_bonus| {
-                            let mut stats: String = format!("Stats:\n");
-                            let mut stats_present = false;
-                            if stat_bonus.proficiency > 0 {
-                                stats_present = true;
-                                stats.push_str(&*format!(
-                                    "    | Combat Proficiency: {}\n",
-                                    stat_bonus.proficiency
-                                ));
-                            }
-                            if stat_bonus.damage_bonus > 0 {
-                                stats_present = true;
-                                stats.push_str(&*format!(
-                                    "    | Damage: {}\n",
-                                    stat_bonus.damage_bonus
-                                ));
-                            }
-                            if stat_bonus.damage_res > 0 {
-                                stats_present = true;
-                                stats.push_str(&*format!(
-                                    "    | Damage Resistance: {}\n",
-                                    stat_bonus.damage_res
-                                ));
-                            }
-                            if stat_bonus.max_health > 0 {
-                                stats_present = true;
-                                stats.push_str(&*format!(
-                                    "    | Max HP: {}\n",
-                                    stat_bonus.max_health
-                                ));
-                            }
-                            if stats_present {
-                                Some(stats)
-                            } else {
-                                None
-                            }
-                        })
-                        .flatten();
-                    if let Some(stat_bonus) = stats_text {
+                    // If applicable, spawn the stats, effects and evolution text:
+                    let style = TextStyle {
+                        font: assets.font(&FontId::MSBold),
+                        font_size: 60.0,
+                        color: Color::ANTIQUE_WHITE,
+                    };
+                    let compared_to =
+                        item.wearable
+                            .filter(|_| equipped.is_none())
+                            .and_then(|slot| {
+                                equipped_items
+                                    .iter()
+                                    .find(|(_, other)| other.slot == slot)
+                                    .map(|(equipped_item, _)| equipped_item)
+                            });
+                    let name_of = |id: &ItemId| {
+                        items_data
+                            .items
+                            .iter()
+                            .find(|(_, other)| other.id == *id)
+                            .map(|(_, other)| other.name_in(era).to_string())
+                            .unwrap_or_else(|| id.to_string())
+                    };
+                    let sections = stats_sections(item, compared_to, era, &style)
+                        .into_iter()
+                        .chain(effect_sections(item, &style))
+                        .chain(evolution_sections(item, &items_data, &name_of, &style))
+                        .collect::<Vec<_>>();
+                    if !sections.is_empty() {
                         parent
                             .spawn_bundle(Text2dBundle {
-                                // Default text, will probably never be seen:
-                                text: Text::from_section(
-                                    stat_bonus,
-                                    TextStyle {
-                                        font: assets.font(&FontId::MSBold),
-                                        font_size: 60.0,
-                                        color: Color::ANTIQUE_WHITE,
-                                    },
-                                )
-                                .with_alignment(text_alignment),
+                                text: Text::from_sections(sections).with_alignment(text_alignment),
                                 // The max size that it should fit in:
                                 text_2d_bounds: Text2dBounds {
                                     size: Vec2::new(
                                         text_bounds.x * layout.text_factor,
-                                        text_bounds.y * layout.text_factor,
+                                        STATS_MAX_HEIGHT * layout.text_factor,
                                     ),
                                 },
                                 transform: Transform::from_translation(Vec3::new(0., 0., 1.0))
@@ -332,3 +357,131 @@ pub fn update_mouse_over_item_info_style_position_system(
         };
     }
 }
+
+/// === Tooltip text ===
+
+/// The stats of a stat bonus by name, including the ones that are zero.
+fn named_stats(stats: &StatBonus) -> [(&'static str, i32); 5] {
+    [
+        ("Health", stats.health),
+        ("Max HP", stats.max_health),
+        ("Combat Proficiency", stats.proficiency),
+        ("Damage", stats.damage_bonus),
+        ("Damage Resistance", stats.damage_res),
+    ]
+}
+
+fn heading(text: String, style: &TextStyle) -> TextSection {
+    TextSection::new(text, style.clone())
+}
+
+/// A line with a signed value, green if it is good for the hero and red if it is bad.
+fn signed_line(name: &str, value: i32, good_when_positive: bool, style: &TextStyle) -> TextSection {
+    let good = (value > 0) == good_when_positive;
+    TextSection::new(
+        format!("    | {}: {:+}\n", name, value),
+        TextStyle {
+            color: if good { GOOD } else { BAD },
+            ..style.clone()
+        },
+    )
+}
+
+/// Every stat the item changes, up or down. For a wearable item, also how it compares to the item
+/// that is in its slot now. Equipping doesn't heal, so health is left out of the comparison.
+fn stats_sections(
+    item: &Item,
+    compared_to: Option<&Item>,
+    era: usize,
+    style: &TextStyle,
+) -> Vec<TextSection> {
+    let mut sections = vec![];
+    let stats = item.stat_bonuses.unwrap_or_default();
+    let changed = named_stats(&stats)
+        .into_iter()
+        .filter(|(_, value)| *value != 0)
+        .collect::<Vec<_>>();
+    if !changed.is_empty() {
+        sections.push(heading("Stats:\n".to_string(), style));
+        for (name, value) in changed {
+            sections.push(signed_line(name, value, true, style));
+        }
+    }
+    if let Some(other) = compared_to {
+        sections.push(heading(
+            format!("Compared to {}:\n", other.name_in(era)),
+            style,
+        ));
+        let other_stats = other.stat_bonuses.unwrap_or_default();
+        let differences = named_stats(&stats)
+            .into_iter()
+            .zip(named_stats(&other_stats))
+            // Health comes first.
+            .skip(1)
+            .map(|((name, value), (_, other_value))| (name, value - other_value))
+            .filter(|(_, difference)| *difference != 0)
+            .collect::<Vec<_>>();
+        if differences.is_empty() {
+            sections.push(heading("    | No difference\n".to_string(), style));
+        }
+        for (name, difference) in differences {
+            sections.push(signed_line(name, difference, true, style));
+        }
+    }
+    sections
+}
+
+/// What the item's timed effect does, to whom, and for how long.
+fn effect_sections(item: &Item, style: &TextStyle) -> Vec<TextSection> {
+    let Some(effect) = &item.temporary_effect else {
+        return vec![];
+    };
+    let target = if effect.on_hero { "hero" } else { "enemy" };
+    let mut sections = vec![heading(
+        format!("Effect on the {} for {}s:\n", target, effect.time),
+        style,
+    )];
+    for (name, value) in [
+        ("Max HP", effect.max_health_mod),
+        ("Combat Proficiency", effect.combat_prof_mod),
+        ("Damage", effect.damage_mod),
+        ("Damage Resistance", effect.damage_res_mod),
+    ] {
+        if value != 0 {
+            // Whatever weakens the enemy is good for the hero.
+            sections.push(signed_line(name, value, effect.on_hero, style));
+        }
+    }
+    sections
+}
+
+/// The items of the evolution chain that this item unlocks or helps grow.
+fn evolution_sections(
+    item: &Item,
+    items_data: &ItemsData,
+    name_of: &dyn Fn(&ItemId) -> String,
+    style: &TextStyle,
+) -> Vec<TextSection> {
+    let mut lines = vec![];
+    for rule in EVOLUTION_RULES {
+        if let Some((_, count)) = rule.unlocked_by.iter().find(|(id, _)| *id == item.id) {
+            lines.push(format!(
+                "    | Unlocks {} (needs {})\n",
+                name_of(&rule.item),
+                count
+            ));
+        }
+        if rule.growth_sources(items_data).contains(&item.id) {
+            lines.push(format!("    | Helps {} grow\n", name_of(&rule.item)));
+        }
+    }
+    if POPULATION_SOURCES.iter().any(|(id, _)| *id == item.id) {
+        lines.push("    | Feeds the population\n".to_string());
+    }
+    if lines.is_empty() {
+        return vec![];
+    }
+    let mut sections = vec![heading("Evolution:\n".to_string(), style)];
+    sections.extend(lines.into_iter().map(|line| heading(line, style)));
+    sections
+}
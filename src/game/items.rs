@@ -1,14 +1,20 @@
+use std::collections::HashMap;
 use std::fmt::Formatter;
 
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::game::TextureId;
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::game::{FontId, TextureId};
 use crate::mouse::MouseInteractive;
-use crate::positioning::Coords;
+use crate::positioning::{CellMask, Coords, Depth, Dimens, GridData, Pos};
 
+use super::auto_use::AutoUsePolicy;
+use super::backpack::Backpack;
 use super::combat::Hero;
+use super::feed::{AddFeedItemEvent, MessageColour};
 use super::item_info_system::TooltipBg;
+use super::spawn_item_system::StackItemEvent;
 use super::timed_effect::{apply_timed_modifier, TemporaryModifier};
 
 /// Marker component. This item is currently in the crafting window.
@@ -53,6 +59,39 @@ pub struct Item {
     pub wearable: Option<EquipmentSlot>,
     pub stat_bonuses: Option<StatBonus>,
     pub temporary_effect: Option<TemporaryModifier>,
+    /// Width and height on the grid. Not part of the item itself in the data file; it is copied
+    /// over from the dimensions listed next to it when the items data is loaded.
+    #[serde(skip, default = "Dimens::unit")]
+    pub dimens: Dimens,
+    /// If the item isn't a solid rectangle, the cells within its dimensions that it covers.
+    pub shape: Option<CellMask>,
+    /// How many of this item fit in one cell. Items that aren't listed with a stack size don't
+    /// stack at all.
+    #[serde(default = "Item::default_max_stack")]
+    pub max_stack: usize,
+    /// Using this item adds this many columns and rows to the backpack grid.
+    #[serde(default)]
+    pub backpack_upgrade: Option<Dimens>,
+    /// What kind of item this is. Rules that care about a whole group of items, rather than one
+    /// particular item, should look at these.
+    #[serde(default)]
+    pub tags: Vec<ItemTag>,
+    /// For a `Tool`, how much food the era gathers with it as its best tool. Higher is better.
+    #[serde(default)]
+    pub tool_tier: usize,
+    /// How the item looks in particular eras, keyed by the timepoint of the era's backpack.
+    /// Whatever an era leaves out is taken from the fields above.
+    #[serde(default)]
+    pub eras: HashMap<usize, EraLook>,
+}
+
+/// The name, description and sprite that an item has in one era instead of its usual ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EraLook {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub texture_id: Option<TextureId>,
 }
 
 impl Default for Item {
@@ -65,10 +104,62 @@ impl Default for Item {
             wearable: None,
             stat_bonuses: Default::default(),
             temporary_effect: Default::default(),
+            dimens: Dimens::unit(),
+            shape: None,
+            max_stack: 1,
+            backpack_upgrade: None,
+            tags: vec![],
+            tool_tier: 0,
+            eras: HashMap::new(),
         }
     }
 }
 
+impl Item {
+    /// The space this item takes up when its bottom-left corner is at the given position.
+    pub fn footprint_at(&self, pos: Pos) -> Coords {
+        Coords::new(pos, self.dimens).with_mask(self.shape)
+    }
+
+    pub fn has_tag(&self, tag: ItemTag) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    /// The name of the item in the backpack of the given era.
+    pub fn name_in(&self, era: usize) -> &str {
+        self.eras
+            .get(&era)
+            .and_then(|look| look.name.as_deref())
+            .unwrap_or(&self.name)
+    }
+
+    /// The description of the item in the backpack of the given era.
+    pub fn description_in(&self, era: usize) -> &str {
+        self.eras
+            .get(&era)
+            .and_then(|look| look.description.as_deref())
+            .unwrap_or(&self.description)
+    }
+
+    /// The sprite of the item in the backpack of the given era.
+    pub fn texture_in(&self, era: usize) -> TextureId {
+        self.eras
+            .get(&era)
+            .and_then(|look| look.texture_id)
+            .unwrap_or(self.texture_id)
+    }
+
+    fn default_max_stack() -> usize {
+        1
+    }
+
+    /// Split a number of these items into the stacks they would take up, fullest first.
+    pub fn split_into_stacks(&self, count: usize) -> impl Iterator<Item = usize> {
+        let max_stack = self.max_stack.max(1);
+        (0..count.div_ceil(max_stack)).map(move |i| max_stack.min(count - i * max_stack))
+    }
+}
+
 #[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct StatBonus {
     pub health: i32,
@@ -145,6 +236,7 @@ pub enum ItemId {
     ScrollKnowledge1,
     ScrollKnowledge2,
     ScrollKnowledge3,
+    ScrollTailoring,
     // ^ don't delete now for backward compatibility
     StoneTool,
     BronzeTool,
@@ -176,6 +268,45 @@ pub enum ItemId {
     Industrialization,
 }
 
+/// Broad categories of items. An item can have any number of them.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ItemTag {
+    Weapon,
+    Armour,
+    Consumable,
+    Ingredient,
+    Food,
+    Tool,
+    Production,
+    Economy,
+    Knowledge,
+    Government,
+    /// Owning one of these in the modern era wins the game.
+    Victory,
+}
+
+impl ItemTag {
+    pub const ALL: [ItemTag; 11] = [
+        ItemTag::Weapon,
+        ItemTag::Armour,
+        ItemTag::Consumable,
+        ItemTag::Ingredient,
+        ItemTag::Food,
+        ItemTag::Tool,
+        ItemTag::Production,
+        ItemTag::Economy,
+        ItemTag::Knowledge,
+        ItemTag::Government,
+        ItemTag::Victory,
+    ];
+}
+
+impl std::fmt::Display for ItemTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 impl std::fmt::Display for ItemId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -189,84 +320,240 @@ pub enum EquipmentSlot {
     Shield,
 }
 
+/// Added to items that the hero is wearing. An equipped item sits in its slot on the hero widget
+/// instead of in a backpack, so it has no Backpack component.
 #[derive(Component, Debug)]
 pub struct EquippedItem {
     pub slot: EquipmentSlot,
-    pub name: String,
-    pub stat_bonus: StatBonus,
 }
 
-/// Store how many items stacked in one grid.
-#[derive(Component)]
+/// Store how many items stacked in one grid. Every item in the backpack has one, a lone item
+/// is a stack of 1.
+#[derive(Component, Debug, Copy, Clone)]
 pub struct ItemStack(pub usize);
 
+impl ItemStack {
+    /// How many more items of the given kind fit on this stack.
+    pub fn room(&self, item: &Item) -> usize {
+        item.max_stack.max(1).saturating_sub(self.0)
+    }
+}
+
+/// How many items an entity stands for. Items that somehow lack an ItemStack count as one.
+pub fn stack_count(stack: Option<&ItemStack>) -> usize {
+    stack.map(|ItemStack(count)| *count).unwrap_or(1)
+}
+
+/// Take a number of items off a stack, and despawn the stack once nothing is left of it.
+pub fn take_from_stack(
+    commands: &mut Commands,
+    e: Entity,
+    stack: Option<&ItemStack>,
+    count: usize,
+) {
+    let left = stack_count(stack).saturating_sub(count);
+    if left == 0 {
+        commands.entity(e).despawn_recursive();
+    } else {
+        commands.entity(e).insert(ItemStack(left));
+    }
+}
+
+/// How many quarter turns clockwise an item has been rotated by the player. The item's Coords
+/// already hold the rotated footprint, this is only needed to draw the sprite the right way round.
+#[derive(Component, Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ItemRotation(pub u8);
+
+impl ItemRotation {
+    /// The same rotation turned one more quarter clockwise.
+    #[must_use]
+    pub fn turned(self) -> Self {
+        ItemRotation((self.0 + 1) % 4)
+    }
+
+    pub fn as_quat(self) -> Quat {
+        Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2 * self.0 as f32)
+    }
+}
+
+/// SHIFT-click an item to have the hero wear it or use it.
 pub fn consume_item(
     mut commands: Commands,
     mut hero: ResMut<Hero>,
-    items: Query<(Entity, &Item, &MouseInteractive)>,
-    equipped_items_query: Query<&EquippedItem>,
+    grid: Res<GridData>,
+    items: Query<(
+        Entity,
+        &Item,
+        &Coords,
+        &Backpack,
+        &MouseInteractive,
+        Option<&ItemStack>,
+    )>,
+    equipped_items_query: Query<(Entity, &Item, &EquippedItem)>,
     tooltips: Query<Entity, With<TooltipBg>>,
+    mut ew_stack_item: EventWriter<StackItemEvent>,
 ) {
-    for (e, item, interactive) in items.iter() {
-        if interactive.shift_clicked {
-            // Unequip any items already equipped that the new item can override.
-            if let Some(new_slot) = item.wearable {
-                for currently_equipped_item in equipped_items_query.iter() {
-                    if currently_equipped_item.slot == new_slot {
-                        hero.combat_stats.max_health -=
-                            currently_equipped_item.stat_bonus.max_health;
-                        hero.combat_stats.proficiency -=
-                            currently_equipped_item.stat_bonus.proficiency;
-                        hero.combat_stats.damage_res -=
-                            currently_equipped_item.stat_bonus.damage_res;
-                        hero.combat_stats.damage_bonus -=
-                            currently_equipped_item.stat_bonus.damage_bonus;
-                    }
-                }
-                // Create a new entity with an EquippedItem component to represent Equipped Items on the Hero
-                if let Some(stats) = item.stat_bonuses {
-                    commands.spawn().insert(EquippedItem {
-                        slot: new_slot,
-                        name: item.clone().name,
-                        stat_bonus: StatBonus {
-                            health: 0,
-                            max_health: stats.max_health,
-                            proficiency: stats.proficiency,
-                            damage_bonus: stats.damage_bonus,
-                            damage_res: stats.damage_res,
-                        },
-                    });
-                }
+    for (e, item, coords, backpack, interactive, stack) in items.iter() {
+        if !interactive.shift_clicked {
+            continue;
+        }
+        if item.wearable.is_some() {
+            swap_equipment(
+                &mut commands,
+                &mut hero,
+                &grid,
+                e,
+                item,
+                (backpack.0, *coords),
+                &equipped_items_query,
+                &mut ew_stack_item,
+            );
+            for tooltip in tooltips.iter() {
+                commands.entity(tooltip).despawn_recursive();
             }
+        } else {
+            use_item(&mut commands, &mut hero, e, item, stack, &tooltips);
+        }
+    }
+}
 
-            if let Some(stats) = item.stat_bonuses {
-                hero.combat_stats.health = (hero.combat_stats.health + stats.health)
-                    .clamp(0, hero.combat_stats.max_health);
-                hero.combat_stats.max_health += stats.max_health;
-                hero.combat_stats.proficiency += stats.proficiency;
-                hero.combat_stats.damage_res += stats.damage_res;
-                hero.combat_stats.damage_bonus += stats.damage_bonus;
-
-                commands.entity(e).despawn_recursive();
-                for tooltip in tooltips.iter() {
-                    commands.entity(tooltip).despawn_recursive();
-                }
-            }
+/// Let the hero use an item, and apply its stat bonuses and temporary effects. Items that have any
+/// effect are used up, one off the top of their stack. Wearable items go through swap_equipment
+/// instead.
+pub fn use_item(
+    commands: &mut Commands,
+    hero: &mut Hero,
+    e: Entity,
+    item: &Item,
+    stack: Option<&ItemStack>,
+    tooltips: &Query<Entity, With<TooltipBg>>,
+) {
+    if let Some(stats) = item.stat_bonuses {
+        hero.combat_stats.health =
+            (hero.combat_stats.health + stats.health).clamp(0, hero.combat_stats.max_health);
+        hero.combat_stats.max_health += stats.max_health;
+        hero.combat_stats.proficiency += stats.proficiency;
+        hero.combat_stats.damage_res += stats.damage_res;
+        hero.combat_stats.damage_bonus += stats.damage_bonus;
+    }
 
-            if let Some(modifier) = item.clone().temporary_effect {
-                apply_timed_modifier(modifier, &mut commands);
-                commands.entity(e).despawn_recursive();
-                for tooltip in tooltips.iter() {
-                    commands.entity(tooltip).despawn_recursive();
-                }
-            }
+    if item.stat_bonuses.is_some() || item.temporary_effect.is_some() {
+        take_from_stack(commands, e, stack, 1);
+        for tooltip in tooltips.iter() {
+            commands.entity(tooltip).despawn_recursive();
+        }
+    }
+
+    if let Some(modifier) = item.clone().temporary_effect {
+        apply_timed_modifier(modifier, commands);
+    }
+}
+
+/// Put a wearable item from the backpack in its slot on the hero widget. The item that was in the
+/// slot before goes back into the backpack the new item came from: in the spot the new item leaves
+/// behind if it fits there, or in the first free spot otherwise.
+pub fn swap_equipment(
+    commands: &mut Commands,
+    hero: &mut Hero,
+    grid: &GridData,
+    e: Entity,
+    item: &Item,
+    (backpack, coords): (usize, Coords),
+    equipped_items_query: &Query<(Entity, &Item, &EquippedItem)>,
+    ew_stack_item: &mut EventWriter<StackItemEvent>,
+) {
+    let Some(new_slot) = item.wearable else {
+        return;
+    };
+    for (equipped_e, equipped_item, equipped) in equipped_items_query.iter() {
+        if equipped.slot == new_slot && equipped_e != e {
+            remove_stat_bonus(hero, equipped_item);
+            commands.entity(equipped_e).despawn_recursive();
+            ew_stack_item.send(StackItemEvent {
+                item: equipped_item.clone(),
+                count: 1,
+                backpack: Some(backpack),
+                source: None,
+                vacated: Some(coords),
+            });
         }
     }
+    equip_item(commands, hero, grid, e, item);
+}
+
+/// Move a wearable item out of the backpack into its slot on the hero widget, and give the hero its
+/// stats. Whatever is in that slot already should be dealt with first.
+pub fn equip_item(
+    commands: &mut Commands,
+    hero: &mut Hero,
+    grid: &GridData,
+    e: Entity,
+    item: &Item,
+) {
+    let Some(slot) = item.wearable else {
+        return;
+    };
+    let Some(slot_coords) = grid.equipment.get(&slot) else {
+        error!("There is no {:?} slot in the layout.", slot);
+        return;
+    };
+    // The item is shrunk down until its longest side fits in the slot.
+    let scale = slot_coords.dimens.x.min(slot_coords.dimens.y) as f32
+        / item.dimens.x.max(item.dimens.y) as f32;
+    commands
+        .entity(e)
+        .remove::<Backpack>()
+        .remove::<CraftItem>()
+        .insert(EquippedItem { slot })
+        .insert(*slot_coords)
+        .insert(ItemRotation::default())
+        .insert(MouseInteractive::new(slot_coords.dimens.as_vec2(), true))
+        .insert(
+            Transform::from_xyz(
+                grid.calc_x(slot_coords),
+                grid.calc_y(slot_coords),
+                Depth::Item.z(),
+            )
+            .with_scale(Vec3::new(scale, scale, 1.)),
+        );
+    if let Some(stats) = item.stat_bonuses {
+        hero.combat_stats.max_health += stats.max_health;
+        hero.combat_stats.proficiency += stats.proficiency;
+        hero.combat_stats.damage_res += stats.damage_res;
+        hero.combat_stats.damage_bonus += stats.damage_bonus;
+    }
+}
+
+/// Take an equipped item off the hero and put it back in the given backpack. It is up to the caller
+/// to give it a place in the grid.
+pub fn unequip_item(
+    commands: &mut Commands,
+    hero: &mut Hero,
+    e: Entity,
+    item: &Item,
+    backpack: usize,
+) {
+    remove_stat_bonus(hero, item);
+    commands
+        .entity(e)
+        .remove::<EquippedItem>()
+        .insert(Backpack(backpack));
+}
+
+/// Take the stats of an equipped item off the hero.
+pub fn remove_stat_bonus(hero: &mut Hero, item: &Item) {
+    if let Some(stats) = item.stat_bonuses {
+        hero.combat_stats.max_health -= stats.max_health;
+        hero.combat_stats.proficiency -= stats.proficiency;
+        hero.combat_stats.damage_res -= stats.damage_res;
+        hero.combat_stats.damage_bonus -= stats.damage_bonus;
+        hero.combat_stats.health = hero.combat_stats.health.min(hero.combat_stats.max_health);
+    }
 }
 
 pub fn delete_item_system(
     mut commands: Commands,
-    items: Query<(Entity, &MouseInteractive), With<Item>>,
+    items: Query<(Entity, &MouseInteractive), (With<Item>, Without<EquippedItem>)>,
     tooltips: Query<Entity, With<TooltipBg>>,
 ) {
     for (e, interactive) in items.iter() {
@@ -280,14 +567,71 @@ pub fn delete_item_system(
 }
 
 /// Apply a dark scrim to the item that is being dragged.
-pub fn apply_silhouette(mut query: Query<(&mut Sprite, Option<&Silhouette>), With<Item>>) {
-    for (mut sprite, silhouette) in query.iter_mut() {
+/// Items that the hero will use by themselves get a green tint.
+/// Items that don't match the inventory filter are faded out.
+pub fn apply_silhouette(
+    filter: Res<InventoryFilter>,
+    mut query: Query<(
+        &Item,
+        &mut Sprite,
+        Option<&Silhouette>,
+        Option<&AutoUsePolicy>,
+    )>,
+) {
+    for (item, mut sprite, silhouette, auto_use) in query.iter_mut() {
         sprite.color = if silhouette.is_some() {
             Color::rgba(0.1, 0.1, 0.1, 1.)
+        } else if auto_use.is_some() {
+            Color::rgb(0.75, 1., 0.75)
         } else {
             Color::rgb(1., 1., 1.)
         };
+        if !filter.matches(item) {
+            sprite.color.set_a(0.25);
+        }
+    }
+}
+
+/// Resource. When set, only items with this tag are shown at full strength.
+#[derive(Default)]
+pub struct InventoryFilter(pub Option<ItemTag>);
+
+impl InventoryFilter {
+    pub fn matches(&self, item: &Item) -> bool {
+        self.0.map(|tag| item.has_tag(tag)).unwrap_or(true)
+    }
+}
+
+/// The CycleInventoryFilter binding (`T` by default) steps the inventory filter through all the
+/// item tags, and then back to no filter.
+pub fn cycle_inventory_filter(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut filter: ResMut<InventoryFilter>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+) {
+    if !config.just_pressed(InputAction::CycleInventoryFilter, &keys, &buttons, &pads) {
+        return;
     }
+    filter.0 = match filter.0 {
+        None => ItemTag::ALL.first().copied(),
+        Some(tag) => ItemTag::ALL
+            .iter()
+            .skip_while(|other| **other != tag)
+            .nth(1)
+            .copied(),
+    };
+    let message = match filter.0 {
+        Some(tag) => format!("Showing only {} items.", tag),
+        None => "Showing all items.".to_string(),
+    };
+    feed.send(AddFeedItemEvent {
+        message,
+        colour: MessageColour::Neutral,
+        font: FontId::FiraSansItalic,
+    });
 }
 
 pub fn show_item_stack_count(
@@ -13,18 +13,25 @@ pub use self::state::*;
 pub use wingame_system::WinGamePlugin;
 
 pub mod assets;
+pub mod auto_use;
 pub mod backpack;
 pub mod camera;
 pub mod combining_system;
 pub mod components;
+pub mod context_menu;
 pub mod dragging;
+pub mod era_view;
+pub mod grid_cursor;
 pub mod health_bar_system;
 pub mod init;
 pub mod item_info_system;
 pub mod items;
+pub mod recipe_book;
 pub mod recipes;
 pub mod sim;
+pub mod sort_backpack;
 pub mod spawn_item_system;
 pub mod state;
+pub mod tech_tree;
 pub mod timed_effect;
 pub mod wingame_system;
\ No newline at end of file
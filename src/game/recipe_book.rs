@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::ConditionSet;
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::config::data_items::ItemsData;
+use crate::config::data_recipes::RecipesData;
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::recipes::Recipe;
+use crate::game::{
+    stack_count, take_from_stack, AssetStorage, CraftItem, FontId, Item, ItemId, ItemStack,
+    SpawnItemEvent,
+};
+use crate::mouse::MouseInteractive;
+use crate::positioning::{Coords, GridData};
+use crate::states::AppState;
+
+/// Where the recipe book is kept between runs.
+const RECIPE_BOOK_PATH: &str = "saves/recipe_book.ron";
+
+/// Size of the item icons in the recipe book, in points.
+const ICON_SIZE: f32 = 24.;
+
+pub struct RecipeBookPlugin;
+
+impl Plugin for RecipeBookPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RecipeBook::load())
+            .add_event::<FillCraftingGridEvent>()
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(toggle_recipe_book)
+                    .with_system(draw_recipe_book_window)
+                    .with_system(fill_crafting_grid)
+                    .into(),
+            );
+    }
+}
+
+/// Resource. The recipes that the player has crafted at least once, over all runs.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct RecipeBook {
+    /// The ingredients of the discovered recipes, with how many of each. Several recipes can make
+    /// the same item, so a recipe is told apart by what goes into it.
+    pub discovered: Vec<HashMap<ItemId, usize>>,
+    /// Whether the recipe book window is open.
+    #[serde(skip)]
+    pub show_window: bool,
+}
+
+impl RecipeBook {
+    fn load() -> Self {
+        let Ok(bytes) = std::fs::read(RECIPE_BOOK_PATH) else {
+            return RecipeBook::default();
+        };
+        ron::de::from_bytes(&bytes).unwrap_or_else(|e| {
+            warn!("Could not read the recipe book, starting a new one: {}", e);
+            RecipeBook::default()
+        })
+    }
+
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                let dir = std::path::Path::new(RECIPE_BOOK_PATH).parent().unwrap();
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                std::fs::write(RECIPE_BOOK_PATH, text).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            warn!("Could not save the recipe book: {}", e);
+        }
+    }
+
+    pub fn is_discovered(&self, recipe: &Recipe) -> bool {
+        self.discovered.contains(&recipe.ingredient_counts())
+    }
+
+    /// Write down a recipe the player just crafted. New discoveries are saved right away.
+    pub fn discover(&mut self, recipe: &Recipe) {
+        if !self.is_discovered(recipe) {
+            self.discovered.push(recipe.ingredient_counts());
+            self.save();
+        }
+    }
+}
+
+/// Broadcast this to move the ingredients of a recipe from the backpack into the crafting grid.
+pub struct FillCraftingGridEvent(pub Recipe);
+
+/// Marker component for the button next to the crafting grid that opens the recipe book.
+#[derive(Component)]
+pub struct RecipeBookButton;
+
+/// The OpenRecipeBook binding (`J` by default) or the recipe book button opens and closes the
+/// recipe book.
+fn toggle_recipe_book(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    button_query: Query<&MouseInteractive, With<RecipeBookButton>>,
+    mut book: ResMut<RecipeBook>,
+) {
+    let clicked = button_query.iter().any(|button| button.clicked);
+    if config.just_pressed(InputAction::OpenRecipeBook, &keys, &buttons, &pads) || clicked {
+        book.show_window = !book.show_window;
+    }
+}
+
+/// Lists every recipe. Undiscovered ones only show the silhouette of what they make. Clicking a
+/// discovered recipe puts its ingredients in the crafting grid.
+fn draw_recipe_book_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut book: ResMut<RecipeBook>,
+    mut writer: EventWriter<FillCraftingGridEvent>,
+    assets: Res<AssetStorage>,
+    recipes: Res<RecipesData>,
+    items_data: Res<ItemsData>,
+    backpack_in_use: Query<&BackpackInUse>,
+) {
+    if !book.show_window {
+        return;
+    }
+    let era = backpack_in_use
+        .get_single()
+        .map(|BackpackInUse(id)| *id)
+        .unwrap_or_default();
+    let items = items_data
+        .items
+        .iter()
+        .map(|(_, item)| (item.id.clone(), item))
+        .collect::<HashMap<_, _>>();
+    let mut icons = HashMap::new();
+    for item in items.values() {
+        icons
+            .entry(item.texture_id)
+            .or_insert_with(|| egui_context.add_image(assets.texture(&item.texture_id)));
+    }
+    let icon = |id: &ItemId| items.get(id).and_then(|item| icons.get(&item.texture_id));
+    let name = |id: &ItemId| items.get(id).map(|item| item.name_in(era)).unwrap_or("?");
+
+    let mut show_window = book.show_window;
+    egui::Window::new("Recipe book")
+        .open(&mut show_window)
+        .default_width(360.)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("Click a recipe to lay out its ingredients in the crafting area.");
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for recipe in recipes.recipes.iter() {
+                    if !book.is_discovered(recipe) {
+                        ui.horizontal(|ui| {
+                            if let Some(icon) = icon(&recipe.result) {
+                                ui.add(
+                                    egui::Image::new(*icon, [ICON_SIZE, ICON_SIZE])
+                                        .tint(egui::Color32::BLACK),
+                                );
+                            }
+                            ui.label("???");
+                        });
+                        continue;
+                    }
+                    let row = ui.horizontal(|ui| {
+                        if let Some(icon) = icon(&recipe.result) {
+                            ui.image(*icon, [ICON_SIZE, ICON_SIZE]);
+                        }
+                        ui.label(name(&recipe.result));
+                        ui.label("=");
+                        for ingr in recipe.ingredients.iter() {
+                            if let Some(icon) = icon(&ingr.item_id) {
+                                ui.image(*icon, [ICON_SIZE, ICON_SIZE])
+                                    .on_hover_text(name(&ingr.item_id));
+                            }
+                            ui.label(format!("x{}", ingr.quantity));
+                        }
+                    });
+                    if row.response.interact(egui::Sense::click()).clicked() {
+                        writer.send(FillCraftingGridEvent(recipe.clone()));
+                    }
+                }
+            });
+        });
+    book.show_window = show_window;
+}
+
+/// Move the ingredients of a recipe out of the backpack in use and into the crafting grid. Nothing
+/// is moved unless the crafting grid is empty and all of them are in the backpack, as anything
+/// else in the grid would keep the recipe from being crafted.
+fn fill_crafting_grid(
+    mut commands: Commands,
+    mut events: EventReader<FillCraftingGridEvent>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+    mut ew_spawn_item: EventWriter<SpawnItemEvent>,
+    grid: Res<GridData>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<(
+        Entity,
+        &Item,
+        &Backpack,
+        &Coords,
+        Option<&ItemStack>,
+        Option<&CraftItem>,
+    )>,
+) {
+    for FillCraftingGridEvent(recipe) in events.iter() {
+        let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+            error!("There should be only one BackpackInUse component in game.");
+            return;
+        };
+        let in_backpack = items
+            .iter()
+            .filter(|(_, _, backpack, _, _, _)| backpack.0 == *backpack_id)
+            .collect::<Vec<_>>();
+        if in_backpack
+            .iter()
+            .any(|(_, _, _, _, _, craft)| craft.is_some())
+        {
+            feed.send(AddFeedItemEvent {
+                message: "Clear the crafting area before laying out a recipe.".to_string(),
+                colour: MessageColour::Neutral,
+                font: FontId::FiraSansItalic,
+            });
+            continue;
+        }
+        let needed = recipe.ingredient_counts();
+
+        let mut taken = vec![];
+        let mut placements = vec![];
+        let mut takes = vec![];
+        let mut missing = false;
+        'ingredients: for (id, count) in needed.iter() {
+            let mut sources = in_backpack
+                .iter()
+                .filter(|(_, item, _, _, _, craft)| item.id == *id && craft.is_none())
+                .collect::<Vec<_>>();
+            let available = sources
+                .iter()
+                .map(|(_, _, _, _, stack, _)| stack_count(*stack))
+                .sum::<usize>();
+            let Some((_, item, _, _, _, _)) = sources.first() else {
+                missing = true;
+                break;
+            };
+            if available < *count {
+                missing = true;
+                break;
+            }
+            for stack in item.split_into_stacks(*count) {
+                let footprint = item.footprint_at(grid.crafting.pos);
+                let Some(coords) = find_crafting_space(&grid, &footprint, &taken) else {
+                    missing = true;
+                    break 'ingredients;
+                };
+                taken.push(coords);
+                placements.push((*item, coords, stack));
+            }
+            // Take the ingredients off the backpack stacks, smallest stacks first.
+            sources.sort_by_key(|(_, _, _, _, stack, _)| stack_count(*stack));
+            let mut left = *count;
+            for (e, _, _, _, stack, _) in sources {
+                if left == 0 {
+                    break;
+                }
+                let moved = left.min(stack_count(*stack));
+                takes.push((*e, *stack, moved));
+                left -= moved;
+            }
+        }
+        if missing {
+            feed.send(AddFeedItemEvent {
+                message: "The backpack doesn't hold everything for that recipe, or there is no \
+                          room for it in the crafting area."
+                    .to_string(),
+                colour: MessageColour::Neutral,
+                font: FontId::FiraSansItalic,
+            });
+            continue;
+        }
+        for (e, stack, count) in takes {
+            take_from_stack(&mut commands, e, stack, count);
+        }
+        for (item, coords, stack) in placements {
+            let mut evt = SpawnItemEvent::without_anim(item.clone(), coords).stacked(stack);
+            evt.backpack = Some(*backpack_id);
+            ew_spawn_item.send(evt);
+        }
+    }
+}
+
+/// Find the first spot in the crafting grid where something of the given footprint fits. Only the
+/// size and shape of the footprint matter, not where it is.
+pub fn find_crafting_space(
+    grid: &GridData,
+    footprint: &Coords,
+    taken: &[Coords],
+) -> Option<Coords> {
+    for y in 0..grid.crafting.dimens.y {
+        for x in 0..grid.crafting.dimens.x {
+            let coords = Coords {
+                pos: grid.crafting.pos.plus_xy(x, y),
+                ..*footprint
+            };
+            if grid.crafting.encloses(&coords) && !taken.iter().any(|t| coords.overlaps(t)) {
+                return Some(coords);
+            }
+        }
+    }
+    None
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::game::items::ItemId;
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +9,17 @@ pub struct Recipe {
     pub ingredients: Vec<Ingredient>,
 }
 
+impl Recipe {
+    /// How many of each item go into the recipe, adding up ingredients that are listed twice.
+    pub fn ingredient_counts(&self) -> HashMap<ItemId, usize> {
+        let mut counts = HashMap::new();
+        for ingr in self.ingredients.iter() {
+            *counts.entry(ingr.item_id.clone()).or_default() += ingr.quantity.max(0) as usize;
+        }
+        counts
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Ingredient {
     pub item_id: ItemId,
@@ -1,4 +1,5 @@
-use crate::game::sim::event_handling::SimMessageEvent;
+use crate::game::sim::event_handling::{EnemyAbilityEvent, SimMessageEvent};
+use crate::game::timed_effect::TemporaryModifier;
 use crate::game::ItemId;
 use crate::game::{sim::dungeon_components::TextType, StatBonus};
 use bevy::prelude::*;
@@ -72,6 +73,13 @@ pub struct Enemy {
     pub name: String,
     pub enter_combat_text: TextType,
     pub drop_table: DropTable,
+    /// Special moves this enemy can pull off during combat, on top of the regular roll-and-diff
+    /// exchange of blows.
+    #[serde(default)]
+    pub abilities: Vec<EnemyAbility>,
+    /// Bosses are worth drinking flasks for, see `AutoUsePolicy::BeforeBossFight`.
+    #[serde(default)]
+    pub boss: bool,
 }
 
 impl std::fmt::Display for Enemy {
@@ -94,44 +102,286 @@ impl Default for Enemy {
             name: "Empty enemy".to_string(),
             enter_combat_text: TextType::EnterRat,
             drop_table: DropTable::default(),
+            abilities: vec![],
+            boss: false,
         }
     }
 }
 
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct EnemyAbility {
+    pub effect: AbilityEffect,
+    pub trigger: AbilityTrigger,
+    /// Number of combat rounds the ability has to wait after triggering before it can trigger
+    /// again.
+    pub cooldown: u32,
+    /// If true, the ability can trigger at most once per fight.
+    #[serde(default)]
+    pub once: bool,
+    /// Rounds left until the ability is ready again. Runtime state, not read from the data file.
+    #[serde(skip)]
+    pub cooldown_left: u32,
+    /// Whether the ability has triggered already. Runtime state, not read from the data file.
+    #[serde(skip)]
+    pub used: bool,
+}
+
+/// The condition that must hold for an ability to trigger once it is off cooldown.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum AbilityTrigger {
+    /// Triggers every time the ability is off cooldown.
+    Always,
+    /// Percentage chance to trigger each round: between 0 and 100.
+    Chance(u32),
+    /// Triggers when the enemy's health is below the given percentage of its max health.
+    HealthBelow(i32),
+    /// Triggers when the hero's health is below the given percentage of his max health.
+    HeroHealthBelow(i32),
+}
+
+impl AbilityTrigger {
+    fn is_met(&self, enemy: &Combatant, hero: &Combatant, rng: &mut impl Rng) -> bool {
+        match self {
+            AbilityTrigger::Always => true,
+            AbilityTrigger::Chance(percent) => rng.gen_range(1..=100) <= *percent,
+            AbilityTrigger::HealthBelow(percent) => enemy.health * 100 < enemy.max_health * percent,
+            AbilityTrigger::HeroHealthBelow(percent) => {
+                hero.health * 100 < hero.max_health * percent
+            }
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum AbilityEffect {
+    /// Call in another enemy, who steps up to fight once the current one falls.
+    Summon(EnemyId),
+    /// Restore the given amount of health, up to max health.
+    HealSelf(i32),
+    /// Put a timed modifier into play. Use `on_hero: true` for curses on the hero and
+    /// `on_hero: false` for buffs on the enemy itself.
+    ApplyModifier(TemporaryModifier),
+    /// Permanently boost the enemy's own stats for the rest of the fight.
+    Enrage(StatBonus),
+    /// Snatch a random item out of the backpack that is currently in use.
+    StealItem,
+}
+
+/// Everything that came out of a single round of combat, for the caller to pass on.
+#[derive(Debug)]
+pub struct CombatRoundResult {
+    /// Dungeon feed messages, in the order that things happened.
+    pub messages: Vec<TextType>,
+    /// Enemy ability effects that reach outside of the fight itself.
+    pub ability_events: Vec<EnemyAbilityEvent>,
+    pub round: CombatRoundEvent,
+}
+
 pub fn process_combat(
     events: &mut EventWriter<SimMessageEvent>,
-    mut monster: &mut Combatant,
-    mut hero: &mut Combatant,
+    ability_events: &mut EventWriter<EnemyAbilityEvent>,
+    round_events: &mut EventWriter<CombatRoundEvent>,
+    enemy: &mut Enemy,
+    hero: &mut Combatant,
     cmbt_state: &mut CombatState,
 ) {
+    let result = resolve_combat_round(enemy, hero, &mut rand::thread_rng());
+    for text_type in result.messages {
+        events.send(SimMessageEvent(text_type));
+    }
+    for event in result.ability_events {
+        ability_events.send(event);
+    }
+    round_events.send(result.round);
+
+    if hero.health < 1 {
+        *cmbt_state = CombatState::HeroDead;
+    } else if enemy.combat_stats.health < 1 {
+        *cmbt_state = CombatState::EnemyDead;
+    }
+}
+
+/// Run the enemy's abilities and roll the dice for one exchange of blows, applying the results
+/// to both combatants. Doesn't touch the ECS, so it can also be used outside of the game.
+pub fn resolve_combat_round(
+    enemy: &mut Enemy,
+    hero: &mut Combatant,
+    rng: &mut impl Rng,
+) -> CombatRoundResult {
+    let mut messages = vec![];
+    let mut ability_events = vec![];
+    run_enemy_abilities(enemy, hero, rng, &mut messages, &mut ability_events);
+    let monster = &mut enemy.combat_stats;
+    // Snapshot the stats going into the round, so the log shows what the rolls were based on.
+    let hero_before = *hero;
+    let monster_before = *monster;
+
     const DICE: i32 = 12;
-    let mut rng = rand::thread_rng();
-    let monster_roll = rng.gen_range(0..DICE) + monster.proficiency - monster.negative_feedback;
-    let hero_roll = rng.gen_range(0..DICE) + hero.proficiency - hero.negative_feedback;
+    let monster_dice = rng.gen_range(0..DICE);
+    let hero_dice = rng.gen_range(0..DICE);
+    let monster_roll = monster_dice + monster.proficiency - monster.negative_feedback;
+    let hero_roll = hero_dice + hero.proficiency - hero.negative_feedback;
+    let diff = ((monster_roll - hero_roll).abs() as f32 / 2.0).round() as i32;
 
-    if monster_roll > hero_roll {
-        let diff = ((monster_roll - hero_roll) as f32 / 2.0).round() as i32;
+    let (outcome, damage) = if monster_roll > hero_roll {
         let damage = (monster.damage_bonus - hero.damage_res + diff).clamp(1, 500);
         hero.health -= damage;
-        events.send(SimMessageEvent(TextType::CombatHeroHit));
+        messages.push(TextType::CombatHeroHit);
         monster.negative_feedback += 1;
         hero.negative_feedback = 0;
         debug!("Hero hit for {}: HP at {}.", damage, hero.health);
+        (RoundOutcome::HeroHit, damage)
     } else if hero_roll > monster_roll {
-        let diff = ((hero_roll - monster_roll) as f32 / 2.0).round() as i32;
         let damage = (hero.damage_bonus + diff - monster.damage_res).clamp(1, 500);
         monster.health -= damage;
-        events.send(SimMessageEvent(TextType::CombatEnemyHit));
+        messages.push(TextType::CombatEnemyHit);
         debug!("Monster hit for {}: HP at {}.", damage, monster.health);
         monster.negative_feedback = 0;
         hero.negative_feedback += 1;
+        (RoundOutcome::EnemyHit, damage)
     } else {
-        events.send(SimMessageEvent(TextType::CombatNoResolution));
+        messages.push(TextType::CombatNoResolution);
+        (RoundOutcome::NoResolution, 0)
+    };
+
+    CombatRoundResult {
+        messages,
+        ability_events,
+        round: CombatRoundEvent {
+            enemy_name: enemy.name.clone(),
+            hero: hero_before,
+            enemy: monster_before,
+            hero_dice,
+            enemy_dice: monster_dice,
+            outcome,
+            diff,
+            damage,
+        },
     }
+}
 
-    if hero.health < 1 {
-        *cmbt_state = CombatState::HeroDead;
-    } else if monster.health < 1 {
-        *cmbt_state = CombatState::EnemyDead;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundOutcome {
+    /// The enemy rolled higher and hit the hero.
+    HeroHit,
+    /// The hero rolled higher and hit the enemy.
+    EnemyHit,
+    /// Both rolls were equal, nobody got hurt.
+    NoResolution,
+}
+
+/// Broadcast after every exchange of blows, with everything that went into the outcome.
+#[derive(Debug, Clone)]
+pub struct CombatRoundEvent {
+    pub enemy_name: String,
+    /// The hero's stats going into the round.
+    pub hero: Combatant,
+    /// The enemy's stats going into the round.
+    pub enemy: Combatant,
+    /// The bare dice rolls, before proficiency and negative feedback are applied.
+    pub hero_dice: i32,
+    pub enemy_dice: i32,
+    pub outcome: RoundOutcome,
+    /// Half the difference between both rolls, rounded. Added on top of the attacker's damage.
+    pub diff: i32,
+    /// The damage that was actually dealt. Zero if nobody got hit.
+    pub damage: i32,
+}
+
+impl CombatRoundEvent {
+    pub fn hero_roll(&self) -> i32 {
+        self.hero_dice + self.hero.proficiency - self.hero.negative_feedback
+    }
+
+    pub fn enemy_roll(&self) -> i32 {
+        self.enemy_dice + self.enemy.proficiency - self.enemy.negative_feedback
+    }
+
+    /// Human-readable summary of the round, showing how both rolls and the damage came about.
+    pub fn breakdown(&self) -> String {
+        let rolls = format!(
+            "Hero {} ({} {:+} prof {:+} fb) vs {} {} ({} {:+} prof {:+} fb)",
+            self.hero_roll(),
+            self.hero_dice,
+            self.hero.proficiency,
+            -self.hero.negative_feedback,
+            self.enemy_name,
+            self.enemy_roll(),
+            self.enemy_dice,
+            self.enemy.proficiency,
+            -self.enemy.negative_feedback,
+        );
+        match self.outcome {
+            RoundOutcome::HeroHit => format!(
+                "{}: hero takes {} ({} dmg {:+} diff {:+} res)",
+                rolls, self.damage, self.enemy.damage_bonus, self.diff, -self.hero.damage_res
+            ),
+            RoundOutcome::EnemyHit => format!(
+                "{}: {} takes {} ({} dmg {:+} diff {:+} res)",
+                rolls,
+                self.enemy_name,
+                self.damage,
+                self.hero.damage_bonus,
+                self.diff,
+                -self.enemy.damage_res
+            ),
+            RoundOutcome::NoResolution => format!("{}: nobody lands a blow", rolls),
+        }
+    }
+}
+
+/// Tick the cooldowns of the enemy's abilities and fire every ability that is ready and whose
+/// trigger condition holds. Effects that need access to the world are collected as
+/// `EnemyAbilityEvent`s.
+fn run_enemy_abilities(
+    enemy: &mut Enemy,
+    hero: &Combatant,
+    rng: &mut impl Rng,
+    messages: &mut Vec<TextType>,
+    ability_events: &mut Vec<EnemyAbilityEvent>,
+) {
+    for ability in enemy.abilities.iter_mut() {
+        if ability.cooldown_left > 0 {
+            ability.cooldown_left -= 1;
+            continue;
+        }
+        if (ability.once && ability.used) || !ability.trigger.is_met(&enemy.combat_stats, hero, rng)
+        {
+            continue;
+        }
+        debug!("{} uses {:?}.", enemy.name, ability.effect);
+        match &ability.effect {
+            AbilityEffect::Summon(enemy_id) => {
+                ability_events.push(EnemyAbilityEvent::Summon(enemy_id.clone()));
+                messages.push(TextType::EnemySummoned);
+            }
+            AbilityEffect::HealSelf(amount) => {
+                let stats = &mut enemy.combat_stats;
+                stats.health = (stats.health + amount).min(stats.max_health);
+                messages.push(TextType::EnemyHealed);
+            }
+            AbilityEffect::ApplyModifier(modifier) => {
+                ability_events.push(EnemyAbilityEvent::ApplyModifier(modifier.clone()));
+                messages.push(if modifier.on_hero {
+                    TextType::EnemyCursedHero
+                } else {
+                    TextType::EnemyEnraged
+                });
+            }
+            AbilityEffect::Enrage(bonus) => {
+                let stats = &mut enemy.combat_stats;
+                stats.max_health += bonus.max_health;
+                stats.health += bonus.health;
+                stats.proficiency += bonus.proficiency;
+                stats.damage_res += bonus.damage_res;
+                stats.damage_bonus += bonus.damage_bonus;
+                messages.push(TextType::EnemyEnraged);
+            }
+            AbilityEffect::StealItem => {
+                ability_events.push(EnemyAbilityEvent::StealItem);
+            }
+        }
+        ability.used = true;
+        ability.cooldown_left = ability.cooldown;
     }
 }
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::config::config_sim::SimConfig;
+use crate::game::combat::{CombatRoundEvent, RoundOutcome};
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::FontId;
+
+/// How many rounds the combat log remembers before it starts forgetting the oldest ones.
+const MAX_LOGGED_ROUNDS: usize = 200;
+
+/// Resource. Keeps track of the most recent combat rounds.
+#[derive(Default)]
+pub struct CombatLog {
+    /// If true, each round also posts its roll breakdown to the dungeon feed.
+    pub verbose_feed: bool,
+    /// Whether the combat log window is open.
+    pub show_window: bool,
+    pub rounds: VecDeque<CombatRoundEvent>,
+}
+
+pub fn init_combat_log(mut commands: Commands, config: Res<SimConfig>) {
+    commands.insert_resource(CombatLog {
+        verbose_feed: config.verbose_combat_feed,
+        ..default()
+    });
+}
+
+/// The OpenCombatLog binding (`L` by default) opens and closes the combat log window, and the
+/// ToggleVerboseFeed binding (`V` by default) toggles the verbose feed.
+pub fn toggle_combat_log(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut log: ResMut<CombatLog>,
+) {
+    if config.just_pressed(InputAction::OpenCombatLog, &keys, &buttons, &pads) {
+        log.show_window = !log.show_window;
+    }
+    if config.just_pressed(InputAction::ToggleVerboseFeed, &keys, &buttons, &pads) {
+        log.verbose_feed = !log.verbose_feed;
+        info!("Verbose combat feed: {}", log.verbose_feed);
+    }
+}
+
+pub fn record_combat_rounds(
+    mut events: EventReader<CombatRoundEvent>,
+    mut log: ResMut<CombatLog>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+) {
+    for round in events.iter() {
+        if log.verbose_feed {
+            feed.send(AddFeedItemEvent {
+                message: round.breakdown(),
+                font: FontId::FiraSansItalic,
+                colour: MessageColour::Neutral,
+            });
+        }
+        log.rounds.push_back(round.clone());
+        if log.rounds.len() > MAX_LOGGED_ROUNDS {
+            log.rounds.pop_front();
+        }
+    }
+}
+
+pub fn draw_combat_log_window(mut egui_context: ResMut<EguiContext>, mut log: ResMut<CombatLog>) {
+    if !log.show_window {
+        return;
+    }
+    let CombatLog {
+        verbose_feed,
+        show_window,
+        rounds,
+    } = &mut *log;
+    egui::Window::new("Combat log")
+        .open(show_window)
+        .default_width(480.)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.checkbox(verbose_feed, "Post roll breakdowns to the dungeon feed");
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .stick_to_bottom()
+                .show(ui, |ui| {
+                    if rounds.is_empty() {
+                        ui.label("No fights yet.");
+                    }
+                    for round in rounds.iter() {
+                        let colour = match round.outcome {
+                            RoundOutcome::HeroHit => egui::Color32::from_rgb(255, 128, 128),
+                            RoundOutcome::EnemyHit => egui::Color32::from_rgb(128, 255, 128),
+                            RoundOutcome::NoResolution => egui::Color32::GRAY,
+                        };
+                        ui.colored_label(colour, round.breakdown());
+                    }
+                });
+        });
+}
@@ -19,6 +19,17 @@ pub struct Room {
     pub start: bool,
     pub combat: bool,
     pub flavour: Option<TextType>,
+    /// Special rooms do something to the hero's belongings once the hero has looked around.
+    pub effect: Option<RoomEffect>,
+}
+
+/// The mechanical effect of a special room.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoomEffect {
+    /// Brew a random flask from the ingredients in the crafting area.
+    BrewFlask,
+    /// Upgrade a piece of equipment in the backpack, as if it was smithed.
+    UpgradeEquipment,
 }
 
 impl Default for Room {
@@ -34,6 +45,7 @@ impl Default for Room {
             start: false,
             combat: false,
             flavour: None,
+            effect: None,
         }
     }
 }
@@ -83,6 +95,8 @@ impl std::fmt::Display for TimePoint {
     }
 }
 
+/// One floor of the dungeon. The enemies and loot tables line up with the rooms by index; rooms
+/// without a fight hold a default enemy.
 #[derive(Clone)]
 pub struct DungeonLevel {
     pub depth: i32,
@@ -115,6 +129,7 @@ pub enum TextType {
     CombatHeroDied,
     EnteredStartRoom,
     EnteredEndRoom,
+    DungeonCleared,
     // enemy ecounters
     EnterRat,
     EnterGoblinBrat,
@@ -124,6 +139,17 @@ pub enum TextType {
     EnterSkeleton,
     EnterZombie,
     EnterOgreNecromancer,
+    // enemy abilities
+    EnemySummoned,
+    EnemyHealed,
+    EnemyEnraged,
+    EnemyCursedHero,
+    EnemyStoleItem,
+    // special room effects
+    BrewedFlask,
+    NothingToBrew,
+    UpgradedEquipment,
+    NothingToUpgrade,
     // special room flavours
     PlantRoom,
     AlchemyLab,
@@ -141,10 +167,16 @@ impl TextType {
             | TextType::EnterGoblinShieldBearer
             | TextType::EnterSkeleton
             | TextType::EnterZombie => MessageColour::MinorNegative,
+            TextType::EnemyHealed | TextType::EnemyCursedHero => MessageColour::MinorNegative,
+            TextType::EnemySummoned | TextType::EnemyEnraged | TextType::EnemyStoleItem => {
+                MessageColour::MajorNegative
+            }
             TextType::CombatHeroHit => MessageColour::MajorNegative,
             TextType::CombatEnemyHit => MessageColour::MinorPositive,
-            TextType::CombatEnemyDied => MessageColour::MajorPositive,
-            TextType::FoundLoot => MessageColour::MinorPositive,
+            TextType::CombatEnemyDied | TextType::DungeonCleared => MessageColour::MajorPositive,
+            TextType::FoundLoot | TextType::BrewedFlask | TextType::UpgradedEquipment => {
+                MessageColour::MinorPositive
+            }
             _ => MessageColour::Neutral,
         }
     }
@@ -2,12 +2,14 @@ use crate::config::data_enemies::EnemiesData;
 use crate::game::combat::{DropTable, Enemy, EnemyId};
 use crate::game::dungeon_components::TextType;
 use crate::game::sim::dungeon_components::Room;
+use crate::game::StatBonus;
 use bevy::prelude::*;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::hash::Hash;
 
-use super::dungeon_components::{TimePoint, TimePointLevel};
+use super::dungeon_components::{DungeonLevel, RoomEffect, TimePoint, TimePointLevel};
 
 pub const TIMEPOINT_NOW: i32 = 400;
 pub const TIMEPOINT_ANCIENT: i32 = 0;
@@ -23,7 +25,7 @@ pub struct LevelBlueprint {
 /// Contains possible room types, custom loot, custom flavour texts, and monster spawn rates.
 /// One "segment" results in one room generated.
 /// Enemy and room spawn percentages must add up to 100.
-/// NOTE: Custom loot works only in empty rooms. Corridors don't yield loot, enemies have their own loot.
+/// NOTE: Custom loot works only in empty rooms, alchemy labs and armories. Corridors don't yield loot, enemies have their own loot.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SegmentBlueprint {
     pub types: HashMap<RoomType, u32>,
@@ -40,6 +42,14 @@ pub enum RoomType {
     Corridor,
     Start,
     End,
+    /// Like an empty room, but the hero brews a random flask from the ingredients in the crafting
+    /// area.
+    AlchemyLab,
+    /// Like an empty room, but the hero has a piece of equipment in the backpack upgraded.
+    Armory,
+    /// Always a boss fight: one of the segment's enemies that is a boss, or any boss if it has
+    /// none.
+    Lair,
 }
 
 fn gen_timepoint(time: i32) -> TimePoint {
@@ -62,6 +72,113 @@ pub fn generate_level(mut _cmd: &mut Commands) -> TimePointLevel {
     }
 }
 
+/// Roll the rooms, enemies and loot tables of one floor of the dungeon. `scaling` is added to the
+/// stats of every enemy once per level of depth.
+pub fn generate_dungeon_level(
+    blueprint: &LevelBlueprint,
+    enemies: &EnemiesData,
+    scaling: Option<StatBonus>,
+) -> DungeonLevel {
+    let mut level = DungeonLevel {
+        depth: blueprint.depth,
+        rooms: vec![],
+        enemies: vec![],
+        loot: vec![],
+    };
+    for segment in blueprint.segments.iter() {
+        let room_type = pick_weighted(&segment.types).unwrap_or_default();
+        let mut room = match room_type {
+            RoomType::Start => generate_first_room(),
+            RoomType::End => generate_last_room(),
+            RoomType::Corridor => generate_corridor(),
+            RoomType::Fight => generate_fight(),
+            RoomType::Empty => generate_empty(),
+            RoomType::AlchemyLab => generate_special(RoomEffect::BrewFlask, TextType::AlchemyLab),
+            RoomType::Armory => generate_special(RoomEffect::UpgradeEquipment, TextType::Armory),
+            RoomType::Lair => generate_lair(),
+        };
+        if segment.custom_flavour.is_some() {
+            room.flavour = segment.custom_flavour;
+        }
+        let enemy = if room.combat {
+            let enemy_id = if room_type == RoomType::Lair {
+                pick_boss(segment, enemies)
+            } else {
+                segment.enemies.as_ref().and_then(pick_weighted)
+            }
+            .unwrap_or_default();
+            let mut enemy = get_enemy(enemies, enemy_id);
+            if let Some(bonus) = scaling {
+                scale_enemy(&mut enemy, bonus, blueprint.depth);
+            }
+            enemy
+        } else {
+            Enemy::default()
+        };
+        level.rooms.push(room);
+        level.enemies.push(enemy);
+        level.loot.push(
+            segment
+                .custom_loot
+                .clone()
+                .unwrap_or_else(|| blueprint.default_loot.clone()),
+        );
+    }
+    level
+}
+
+/// Pick one of the bosses among the segment's enemies, or any boss at all if there are none.
+fn pick_boss(segment: &SegmentBlueprint, enemies: &EnemiesData) -> Option<EnemyId> {
+    let is_boss = |id: &EnemyId| {
+        enemies
+            .enemies
+            .iter()
+            .any(|enemy| enemy.enemy_id == *id && enemy.boss)
+    };
+    let segment_bosses = segment
+        .enemies
+        .iter()
+        .flatten()
+        .filter(|(id, _)| is_boss(id))
+        .map(|(id, weight)| (id.clone(), *weight))
+        .collect::<HashMap<_, _>>();
+    pick_weighted(&segment_bosses).or_else(|| {
+        let all_bosses = enemies
+            .enemies
+            .iter()
+            .filter(|enemy| enemy.boss)
+            .map(|enemy| (enemy.enemy_id.clone(), 1))
+            .collect::<HashMap<_, _>>();
+        pick_weighted(&all_bosses)
+    })
+}
+
+/// Make an enemy tougher the deeper it lives.
+fn scale_enemy(enemy: &mut Enemy, bonus: StatBonus, depth: i32) {
+    let stats = &mut enemy.combat_stats;
+    stats.health += bonus.health * depth;
+    stats.max_health += bonus.max_health * depth;
+    stats.proficiency += bonus.proficiency * depth;
+    stats.damage_res += bonus.damage_res * depth;
+    stats.damage_bonus += bonus.damage_bonus * depth;
+}
+
+/// Pick a key at random, where the chance of every key is proportional to its weight.
+fn pick_weighted<T: Clone>(weights: &HashMap<T, u32>) -> Option<T> {
+    let total: u32 = weights.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rand::thread_rng().gen_range(0..total);
+    for (key, weight) in weights.iter() {
+        if roll < *weight {
+            return Some(key.clone());
+        }
+        roll -= weight;
+    }
+    None
+}
+
 fn generate_first_room() -> Room {
     Room {
         start: true,
@@ -101,7 +218,29 @@ fn generate_fight() -> Room {
     }
 }
 
-fn get_enemy(enemies: &Res<EnemiesData>, enemy_id: EnemyId) -> Enemy {
+fn generate_special(effect: RoomEffect, flavour: TextType) -> Room {
+    Room {
+        door: true,
+        description: true,
+        search: true,
+        effect: Some(effect),
+        flavour: Some(flavour),
+        ..Default::default()
+    }
+}
+
+fn generate_lair() -> Room {
+    Room {
+        door: true,
+        description: true,
+        search: true,
+        combat: true,
+        flavour: Some(TextType::LairEntrance),
+        ..Default::default()
+    }
+}
+
+pub fn get_enemy(enemies: &EnemiesData, enemy_id: EnemyId) -> Enemy {
     if let Some(nmy) = enemies
         .enemies
         .clone()
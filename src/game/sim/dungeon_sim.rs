@@ -1,14 +1,16 @@
 use super::dungeon_components::TimePointLevel;
+use crate::config::config_input::{InputAction, InputConfig};
 use crate::config::config_sim::SimConfig;
 use crate::config::data_blueprint::BlueprintData;
 use crate::config::data_enemies::EnemiesData;
 use crate::game::backpack::{BackpackInUse, SwitchBackpackEvent};
 use crate::game::combat::{DropTable, EnemyId};
 use crate::game::event_handling::SimMessageEvent;
-use crate::game::sim::combat::{process_combat, CombatState, Enemy, Hero};
+use crate::game::sim::combat::{process_combat, CombatRoundEvent, CombatState, Enemy, Hero};
 use crate::game::sim::dungeon_components::{DungeonLevel, TextType};
-use crate::game::sim::dungeon_gen::generate_level;
-use crate::game::sim::event_handling::SimLootEvent;
+use crate::game::sim::dungeon_gen::{generate_dungeon_level, generate_level, LevelBlueprint};
+use crate::game::sim::event_handling::{EnemyAbilityEvent, RoomEffectEvent, SimLootEvent};
+use crate::game::timed_effect::{clear_enemy_modifiers, TemporaryModifier};
 use crate::game::{GameResult, ItemId};
 use crate::AppState;
 use bevy::prelude::*;
@@ -30,6 +32,13 @@ pub struct DungeonState {
     pub running: bool,
     pub combat_state: CombatState,
     pub round: i32,
+    /// Enemies summoned mid-fight. They step up one by one once the current enemy falls.
+    pub reinforcements: Vec<Enemy>,
+    /// Index into `BlueprintData.levels` of the floor that the hero is exploring.
+    pub depth: i32,
+    /// The floor that the hero is exploring. None once the dungeon has been cleared.
+    pub dungeon_level: Option<DungeonLevel>,
+    pub cur_room_idx: usize,
 }
 
 #[derive(Component)]
@@ -39,6 +48,7 @@ pub fn init_dungeon(
     mut commands: Commands,
     params: Res<SimConfig>,
     dungeon_bp: Res<BlueprintData>,
+    enemy_data: Res<EnemiesData>,
 ) {
     let mut state = DungeonState {
         max_depth: dungeon_bp.levels.len() as i32 - 1,
@@ -48,6 +58,13 @@ pub fn init_dungeon(
         running: true,
         combat_state: CombatState::Init,
         round: 0,
+        reinforcements: vec![],
+        depth: 0,
+        dungeon_level: dungeon_bp
+            .levels
+            .first()
+            .map(|bp| generate_dungeon_level(bp, &enemy_data, params.enemy_scaling)),
+        cur_room_idx: 0,
     };
     state.current_level = Option::from(generate_level(&mut commands));
     commands.insert_resource(state);
@@ -101,6 +118,177 @@ pub fn tick_timepoint(
     }
 }
 
+/// Walk the hero through the rooms of the current floor, one step every `msg_cooldown`. After the
+/// last room the hero takes the stairs down to the next floor.
+pub fn tick_dungeon(
+    mut msg_events: EventWriter<SimMessageEvent>,
+    mut loot_events: EventWriter<SimLootEvent>,
+    mut ability_events: EventWriter<EnemyAbilityEvent>,
+    mut round_events: EventWriter<CombatRoundEvent>,
+    mut effect_events: EventWriter<RoomEffectEvent>,
+    dungeon_bp: Res<BlueprintData>,
+    enemy_data: Res<EnemiesData>,
+    config: Res<SimConfig>,
+    time: Res<Time>,
+    mut state: ResMut<DungeonState>,
+    mut hero: ResMut<Hero>,
+    mut enemy: ResMut<Enemy>,
+    modifiers: Query<(Entity, &TemporaryModifier)>,
+    mut cmd: Commands,
+    mut victory: ResMut<State<GameResult>>,
+) {
+    if !state.running || !state.msg_cooldown.tick(time.delta()).just_finished() {
+        return;
+    }
+    if state.combat_state == CombatState::HeroDead {
+        msg_events.send(SimMessageEvent(TextType::CombatHeroDied));
+        if victory.current().clone() == GameResult::Won {
+            victory.set(GameResult::Lost).unwrap();
+        }
+        cmd.insert_resource(NextState(AppState::GameEnded));
+        halt_dungeon_sim(&mut state);
+        return;
+    }
+    let state = &mut *state;
+    let Some(level) = state.dungeon_level.as_mut() else {
+        return;
+    };
+    let idx = state.cur_room_idx;
+    if idx >= level.rooms.len() {
+        let next_depth = state.depth + 1;
+        if let Some(blueprint) = dungeon_bp.levels.get(next_depth as usize) {
+            descend(state, next_depth, blueprint, &enemy_data, &config);
+        } else {
+            info!("Dungeon cleared!");
+            msg_events.send(SimMessageEvent(TextType::DungeonCleared));
+            state.dungeon_level = None;
+            halt_dungeon_sim(state);
+        }
+        return;
+    }
+
+    let room = &mut level.rooms[idx];
+    if room.init {
+        room.init = false;
+        let text_type = if room.start {
+            room.start = false;
+            if state.depth == 0 {
+                TextType::RoomStart
+            } else {
+                TextType::EnteredStartRoom
+            }
+        } else if room.end {
+            room.end = false;
+            TextType::RoomEnd
+        } else if room.corridor {
+            TextType::Corridor
+        } else {
+            TextType::Door
+        };
+        msg_events.send(SimMessageEvent(text_type));
+    } else if room.description {
+        room.description = false;
+        msg_events.send(SimMessageEvent(
+            room.flavour.unwrap_or(TextType::EnteredRoom),
+        ));
+    } else if let Some(effect) = room.effect.take() {
+        effect_events.send(RoomEffectEvent(effect));
+    } else if room.combat {
+        match state.combat_state {
+            CombatState::Init | CombatState::Ended => {
+                clear_enemy_modifiers(&modifiers, &mut cmd);
+                *enemy = level.enemies[idx].clone();
+                debug!("Entering combat with {}", *enemy);
+                msg_events.send(SimMessageEvent(enemy.enter_combat_text));
+                state.combat_state = CombatState::InProgress;
+            }
+            CombatState::InProgress => process_combat(
+                &mut msg_events,
+                &mut ability_events,
+                &mut round_events,
+                &mut enemy,
+                &mut hero.combat_stats,
+                &mut state.combat_state,
+            ),
+            CombatState::EnemyDead => {
+                // Give summoned reinforcements the chance to step up first.
+                if state.reinforcements.is_empty() {
+                    msg_events.send(SimMessageEvent(TextType::CombatEnemyDied));
+                    room.combat = false;
+                    state.combat_state = CombatState::Init;
+                }
+            }
+            CombatState::HeroDead => {}
+        }
+    } else if room.search {
+        room.search = false;
+        room.post_search = true;
+        let fought = level.enemies[idx].enemy_id != EnemyId::None;
+        msg_events.send(SimMessageEvent(if fought {
+            TextType::SearchingBody
+        } else {
+            TextType::SearchingRoom
+        }));
+    } else if room.post_search {
+        room.post_search = false;
+        let table = if level.enemies[idx].enemy_id != EnemyId::None {
+            &level.enemies[idx].drop_table
+        } else {
+            &level.loot[idx]
+        };
+        let loot = pick_loot_from_drop_table(table);
+        if loot.is_empty() {
+            msg_events.send(SimMessageEvent(TextType::FoundNothing));
+        } else {
+            msg_events.send(SimMessageEvent(TextType::FoundLoot));
+            for item_id in loot {
+                loot_events.send(SimLootEvent(item_id));
+            }
+        }
+    } else {
+        state.cur_room_idx += 1;
+    }
+}
+
+/// Take the stairs down to the next floor, generating it on the way.
+fn descend(
+    state: &mut DungeonState,
+    depth: i32,
+    blueprint: &LevelBlueprint,
+    enemy_data: &EnemiesData,
+    config: &SimConfig,
+) {
+    info!("Descending to depth {}.", depth);
+    state.depth = depth;
+    state.dungeon_level = Some(generate_dungeon_level(
+        blueprint,
+        enemy_data,
+        config.enemy_scaling,
+    ));
+    state.cur_room_idx = 0;
+}
+
+/// When the current enemy is slain but it summoned help, the next reinforcement takes its place
+/// and the fight goes on.
+pub fn engage_reinforcements(
+    mut msg_events: EventWriter<SimMessageEvent>,
+    mut state: ResMut<DungeonState>,
+    mut enemy: ResMut<Enemy>,
+    modifiers: Query<(Entity, &TemporaryModifier)>,
+    mut cmd: Commands,
+) {
+    if state.combat_state != CombatState::EnemyDead {
+        return;
+    }
+    if let Some(next) = state.reinforcements.pop() {
+        debug!("Reinforcement steps up: {}", next);
+        msg_events.send(SimMessageEvent(next.enter_combat_text));
+        clear_enemy_modifiers(&modifiers, &mut cmd);
+        *enemy = next;
+        state.combat_state = CombatState::InProgress;
+    }
+}
+
 pub fn halt_dungeon_sim(state: &mut DungeonState) {
     info!("Halting dungeon sim.");
     state.running = false;
@@ -111,6 +299,24 @@ pub fn resume_dungeon_sim(mut state: ResMut<DungeonState>) {
     state.running = true;
 }
 
+/// The hero waits after every time jump until the player presses the ContinueDungeon binding
+/// (space by default).
+pub fn continue_dungeon_sim(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    state: ResMut<DungeonState>,
+) {
+    if config.just_pressed(InputAction::ContinueDungeon, &keys, &buttons, &pads)
+        && !state.running
+        && state.dungeon_level.is_some()
+        && state.combat_state != CombatState::HeroDead
+    {
+        resume_dungeon_sim(state);
+    }
+}
+
 fn pick_loot_from_drop_table(table: &DropTable) -> Vec<ItemId> {
     const MAX_ITEMS: usize = 3;
     let mut result = vec![];
@@ -139,7 +345,7 @@ pub fn manage_continue_prompt(
 
     if state.running {
         text.sections[0].value = "".to_string();
-    } else if !state.running && state.combat_state != CombatState::HeroDead {
-        // text.sections[0].value = "Press SPACE to continue exploring.".to_string();
+    } else if state.dungeon_level.is_some() && state.combat_state != CombatState::HeroDead {
+        text.sections[0].value = "Press SPACE to continue exploring.".to_string();
     }
 }
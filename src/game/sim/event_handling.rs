@@ -2,42 +2,212 @@ use bevy::prelude::*;
 use rand::Rng;
 
 use crate::audio::sound_event::SoundEvent;
+use crate::config::data_enemies::EnemiesData;
 use crate::config::data_items::ItemsData;
 use crate::config::data_layout::LayoutData;
+use crate::config::data_recipes::RecipesData;
 use crate::config::data_texts::TextsData;
-use crate::game::dungeon_components::TextType;
+use crate::game::auto_use::is_consumable;
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::combat::EnemyId;
+use crate::game::dungeon_components::{RoomEffect, TextType};
+use crate::game::dungeon_gen::get_enemy;
+use crate::game::dungeon_sim::DungeonState;
 use crate::game::feed::AddFeedItemEvent;
-use crate::game::{find_free_space, FontId, Item, ItemId, SoundId, SpawnItemEvent};
-use crate::positioning::{Coords, GridData};
+use crate::game::item_info_system::TooltipBg;
+use crate::game::timed_effect::{apply_timed_modifier, TemporaryModifier};
+use crate::game::{
+    stack_count, take_from_stack, CraftItem, FontId, Item, ItemId, ItemStack, SoundId,
+    SpawnItemEvent, StackItemEvent,
+};
+use crate::positioning::Coords;
 
 /// Handle a looting session.
 pub struct SimLootEvent(pub ItemId);
 
 pub fn handle_sim_loot(
     mut events: EventReader<SimLootEvent>,
-    grid: Res<GridData>,
     layout: Res<LayoutData>,
     items_data: Res<ItemsData>,
-    items_query: Query<&Coords, With<Item>>,
-    mut spawn: EventWriter<SpawnItemEvent>,
+    mut stack: EventWriter<StackItemEvent>,
 ) {
-    let mut same_tick_items: Vec<Coords> = Vec::new();
     for SimLootEvent(item_id) in events.iter() {
         trace!("Received sim loot event");
-        if let Some((dimens, item)) = items_data.try_get_item(item_id.clone()) {
+        if let Some((_, item)) = items_data.try_get_item(item_id.clone()) {
             let source = Vec2::new(layout.screen_dimens.x * 0.5, layout.screen_dimens.y + 1.);
-            let free_coords = find_free_space(&grid, dimens, &items_query, &same_tick_items);
-            if let Some(coords) = free_coords {
-                if same_tick_items.contains(&coords) {
-                    let new_free_coords =
-                        find_free_space(&grid, dimens, &items_query, &same_tick_items);
-                    if let Some(coords) = new_free_coords {
-                        spawn.send(SpawnItemEvent::new(item, coords, source, false));
+            stack.send(StackItemEvent {
+                item,
+                count: 1,
+                backpack: None,
+                source: Some(source),
+                vacated: None,
+            });
+        }
+    }
+}
+
+/// An enemy ability went off whose effect reaches outside of the fight itself.
+#[derive(Debug)]
+pub enum EnemyAbilityEvent {
+    Summon(EnemyId),
+    ApplyModifier(TemporaryModifier),
+    StealItem,
+}
+
+pub fn handle_enemy_abilities(
+    mut commands: Commands,
+    mut events: EventReader<EnemyAbilityEvent>,
+    mut write_message: EventWriter<SimMessageEvent>,
+    mut state: ResMut<DungeonState>,
+    enemies: Res<EnemiesData>,
+    backpack_in_use: Query<&BackpackInUse>,
+    items: Query<(Entity, &Backpack, Option<&ItemStack>), (With<Item>, Without<CraftItem>)>,
+    tooltips: Query<Entity, With<TooltipBg>>,
+) {
+    for event in events.iter() {
+        trace!("Received enemy ability event {:?}", event);
+        match event {
+            EnemyAbilityEvent::Summon(enemy_id) => {
+                state
+                    .reinforcements
+                    .push(get_enemy(&enemies, enemy_id.clone()));
+            }
+            EnemyAbilityEvent::ApplyModifier(modifier) => {
+                apply_timed_modifier(modifier.clone(), &mut commands);
+            }
+            EnemyAbilityEvent::StealItem => {
+                let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+                    error!("There should be only one BackpackInUse component in game.");
+                    continue;
+                };
+                let candidates = items
+                    .iter()
+                    .filter(|(_, backpack, _)| backpack.0 == *backpack_id)
+                    .map(|(entity, _, stack)| (entity, stack))
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    continue;
+                }
+                // Thieves only ever grab one item off the top of a stack.
+                let (entity, stack) = candidates[rand::thread_rng().gen_range(0..candidates.len())];
+                take_from_stack(&mut commands, entity, stack, 1);
+                for tooltip in tooltips.iter() {
+                    commands.entity(tooltip).despawn_recursive();
+                }
+                write_message.send(SimMessageEvent(TextType::EnemyStoleItem));
+            }
+        }
+    }
+}
+
+/// The hero entered a special room that does something to the hero's belongings.
+pub struct RoomEffectEvent(pub RoomEffect);
+
+pub fn handle_room_effects(
+    mut commands: Commands,
+    mut events: EventReader<RoomEffectEvent>,
+    mut write_message: EventWriter<SimMessageEvent>,
+    mut write_loot: EventWriter<SimLootEvent>,
+    mut spawn: EventWriter<SpawnItemEvent>,
+    recipes: Res<RecipesData>,
+    items_data: Res<ItemsData>,
+    backpack_in_use: Query<&BackpackInUse>,
+    crafting_items: Query<(Entity, &Item, Option<&ItemStack>), With<CraftItem>>,
+    backpack_items: Query<(Entity, &Item, &Coords, &Backpack), Without<CraftItem>>,
+    tooltips: Query<Entity, With<TooltipBg>>,
+) {
+    let mut rng = rand::thread_rng();
+    for RoomEffectEvent(effect) in events.iter() {
+        trace!("Received room effect event {:?}", effect);
+        match effect {
+            RoomEffect::BrewFlask => {
+                // Any recipe for a consumable whose ingredients are all in the crafting area.
+                let available = crafting_items.iter().collect::<Vec<_>>();
+                let brewable = recipes
+                    .recipes
+                    .iter()
+                    .filter(|recipe| {
+                        items_data
+                            .try_get_item(recipe.result.clone())
+                            .map(|(_, result)| is_consumable(&result))
+                            .unwrap_or(false)
+                    })
+                    .filter(|recipe| {
+                        recipe.ingredients.iter().all(|ingr| {
+                            available
+                                .iter()
+                                .filter(|(_, item, _)| item.id == ingr.item_id)
+                                .map(|(_, _, stack)| stack_count(*stack))
+                                .sum::<usize>()
+                                >= ingr.quantity as usize
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                if brewable.is_empty() {
+                    write_message.send(SimMessageEvent(TextType::NothingToBrew));
+                    continue;
+                }
+                let recipe = brewable[rng.gen_range(0..brewable.len())];
+                for ingr in recipe.ingredients.iter() {
+                    let mut needed = ingr.quantity as usize;
+                    for (e, _, stack) in available
+                        .iter()
+                        .filter(|(_, item, _)| item.id == ingr.item_id)
+                    {
+                        let taken = needed.min(stack_count(*stack));
+                        take_from_stack(&mut commands, *e, *stack, taken);
+                        needed -= taken;
+                        if needed == 0 {
+                            break;
+                        }
                     }
+                }
+                write_loot.send(SimLootEvent(recipe.result.clone()));
+                write_message.send(SimMessageEvent(TextType::BrewedFlask));
+            }
+            RoomEffect::UpgradeEquipment => {
+                let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+                    error!("There should be only one BackpackInUse component in game.");
+                    continue;
+                };
+                // Equipment that a smithing recipe would turn into better equipment.
+                let upgradable = backpack_items
+                    .iter()
+                    .filter(|(_, item, _, backpack)| {
+                        backpack.0 == *backpack_id && item.wearable.is_some()
+                    })
+                    .filter_map(|(e, item, coords, _)| {
+                        recipes
+                            .recipes
+                            .iter()
+                            .filter(|recipe| {
+                                recipe
+                                    .ingredients
+                                    .iter()
+                                    .all(|ingr| ingr.item_id == item.id)
+                            })
+                            .find_map(|recipe| items_data.try_get_item(recipe.result.clone()))
+                            .filter(|(_, result)| result.wearable.is_some())
+                            .map(|(dimens, result)| (e, *coords, dimens, result))
+                    })
+                    .collect::<Vec<_>>();
+                if upgradable.is_empty() {
+                    write_message.send(SimMessageEvent(TextType::NothingToUpgrade));
+                    continue;
+                }
+                let (e, coords, dimens, result) =
+                    upgradable[rng.gen_range(0..upgradable.len())].clone();
+                commands.entity(e).despawn_recursive();
+                for tooltip in tooltips.iter() {
+                    commands.entity(tooltip).despawn_recursive();
+                }
+                if dimens == coords.dimens && result.shape == coords.mask {
+                    spawn.send(SpawnItemEvent::without_anim(result, coords));
                 } else {
-                    same_tick_items.push(coords);
-                    spawn.send(SpawnItemEvent::new(item, coords, source, false));
+                    // It doesn't fit in the old spot, so it drops into the backpack like loot.
+                    write_loot.send(SimLootEvent(result.id));
                 }
+                write_message.send(SimMessageEvent(TextType::UpgradedEquipment));
             }
         }
     }
@@ -84,6 +254,7 @@ pub fn handle_sim_message(
             TextType::CombatHeroDied => Some(SoundId::SlashHit),
             TextType::CombatEnemyDied => Some(SoundId::SlashHit),
             TextType::CombatNoResolution => Some(SoundId::SwordClang),
+            TextType::EnemyStoleItem => Some(SoundId::GoblinAhah),
             TextType::Door => Some(SoundId::DoorCreak),
             _ => None,
         };
@@ -1,4 +1,5 @@
 pub mod combat;
+pub mod combat_log;
 pub mod dungeon_components;
 pub mod dungeon_gen;
 pub mod dungeon_sim;
@@ -0,0 +1,205 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use iyes_loopless::prelude::ConditionSet;
+
+use crate::config::data_recipes::RecipesData;
+use crate::game::backpack::{Backpack, BackpackInUse};
+use crate::game::feed::{AddFeedItemEvent, MessageColour};
+use crate::game::{
+    find_free_space, BeingDragged, CraftItem, FallingItem, FontId, Item, ItemId, ItemTag,
+};
+use crate::mouse::{Mouse, MouseInteractive};
+use crate::positioning::{Coords, GridData};
+use crate::states::AppState;
+
+/// How long it takes an item to slide to its new spot, in seconds.
+const SORT_MOVE_SECONDS: f32 = 0.3;
+
+pub struct SortBackpackPlugin;
+
+impl Plugin for SortBackpackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SortBackpackEvent>().add_system_set(
+            ConditionSet::new()
+                .run_in_state(AppState::InGame)
+                .with_system(click_sort_button)
+                .with_system(sort_backpack)
+                .with_system(animate_sorting_move)
+                .into(),
+        );
+    }
+}
+
+/// Marker component for the button that sorts the backpack.
+#[derive(Component)]
+pub struct SortButton;
+
+/// Broadcast this to repack the backpack in use.
+pub struct SortBackpackEvent;
+
+/// Added to items that are sliding to the spot the sort gave them.
+#[derive(Component)]
+pub struct SortingMove {
+    source: Vec2,
+    target: Vec2,
+    timer: Timer,
+}
+
+/// Items are grouped by tag, in the order the tags are declared in. An item with several tags goes
+/// with the earliest of them, and items without any tags go last.
+fn sort_category(item: &Item) -> (bool, Option<ItemTag>) {
+    (item.tags.is_empty(), item.tags.iter().min().copied())
+}
+
+fn click_sort_button(
+    mouse: Res<Mouse>,
+    button: Query<&MouseInteractive, With<SortButton>>,
+    mut writer: EventWriter<SortBackpackEvent>,
+) {
+    if let Ok(interactive) = button.get_single() {
+        if interactive.clicked && !mouse.is_dragging {
+            writer.send(SortBackpackEvent);
+        }
+    }
+}
+
+/// Repack the items in the backpack in use by category, then tier, then name. The items are put
+/// down one by one in the first spot they fit. If the backpack is too full for that to work in
+/// sorted order, the biggest items go first instead, and if even that fails nothing is moved.
+/// Loot that is still falling in has no spot of its own yet, so there is no sorting until it lands.
+fn sort_backpack(
+    mut commands: Commands,
+    mut events: EventReader<SortBackpackEvent>,
+    mut feed: EventWriter<AddFeedItemEvent>,
+    grid: Res<GridData>,
+    recipes: Res<RecipesData>,
+    backpack_in_use: Query<&BackpackInUse>,
+    falling: Query<(), With<FallingItem>>,
+    mut items: Query<
+        (Entity, &Item, &Backpack, &mut Coords, &Transform),
+        (Without<CraftItem>, Without<BeingDragged>),
+    >,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    if !falling.is_empty() {
+        feed.send(AddFeedItemEvent {
+            message: "Wait for the loot to land before sorting.".to_string(),
+            colour: MessageColour::Neutral,
+            font: FontId::FiraSansItalic,
+        });
+        return;
+    }
+    let Ok(BackpackInUse(backpack_id)) = backpack_in_use.get_single() else {
+        error!("There should be only one BackpackInUse component in game.");
+        return;
+    };
+    let tiers = recipe_tiers(&recipes);
+    let tier = |item: &Item| tiers.get(&item.id).copied().unwrap_or(0);
+
+    let mut to_sort = items
+        .iter()
+        .filter(|(_, _, backpack, _, _)| backpack.0 == *backpack_id)
+        .map(|(e, item, _, coords, _)| (e, item, *coords))
+        .collect::<Vec<_>>();
+    to_sort.sort_by(|(_, a, _), (_, b, _)| {
+        sort_category(a)
+            .cmp(&sort_category(b))
+            .then_with(|| tier(a).cmp(&tier(b)))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let sorted = to_sort
+        .iter()
+        .map(|(e, _, coords)| (*e, *coords))
+        .collect::<Vec<_>>();
+
+    let placements = pack(&grid, &sorted).or_else(|| {
+        let mut biggest_first = sorted.clone();
+        // The sort is stable, so items of the same size keep their sorted order.
+        biggest_first.sort_by_key(|(_, coords)| Reverse(coords.cells().len()));
+        pack(&grid, &biggest_first)
+    });
+    let Some(placements) = placements else {
+        feed.send(AddFeedItemEvent {
+            message: "The backpack is too full to sort.".to_string(),
+            colour: MessageColour::Neutral,
+            font: FontId::FiraSansItalic,
+        });
+        return;
+    };
+
+    for (e, new_coords) in placements {
+        if let Ok((_, _, _, mut coords, transform)) = items.get_mut(e) {
+            if *coords == new_coords {
+                continue;
+            }
+            *coords = new_coords;
+            commands.entity(e).insert(SortingMove {
+                source: transform.translation.truncate(),
+                target: Vec2::new(grid.calc_x(&new_coords), grid.calc_y(&new_coords)),
+                timer: Timer::from_seconds(SORT_MOVE_SECONDS, false),
+            });
+        }
+    }
+}
+
+/// Put every item down in the first free spot that fits its footprint, in the given order.
+/// Returns None if any of them doesn't fit.
+fn pack(grid: &GridData, items: &[(Entity, Coords)]) -> Option<Vec<(Entity, Coords)>> {
+    let mut placed: Vec<Coords> = vec![];
+    let mut placements = vec![];
+    for (e, coords) in items {
+        let new_coords = find_free_space(grid, coords.dimens, coords.mask, &placed, &[])?;
+        placed.push(new_coords);
+        placements.push((*e, new_coords));
+    }
+    Some(placements)
+}
+
+/// How many crafting steps each item is away from raw ingredients. Items that no recipe makes are
+/// tier 0, anything else is one tier above its highest ingredient.
+fn recipe_tiers(recipes: &RecipesData) -> HashMap<ItemId, u32> {
+    fn tier_of(id: &ItemId, recipes: &RecipesData, tiers: &mut HashMap<ItemId, u32>) -> u32 {
+        if let Some(tier) = tiers.get(id) {
+            return *tier;
+        }
+        // Guards against recipes that loop back on themselves.
+        tiers.insert(id.clone(), 0);
+        let tier = recipes
+            .recipes
+            .iter()
+            .filter(|recipe| recipe.result == *id)
+            .flat_map(|recipe| recipe.ingredients.iter())
+            .map(|ingr| tier_of(&ingr.item_id, recipes, tiers) + 1)
+            .max()
+            .unwrap_or(0);
+        tiers.insert(id.clone(), tier);
+        tier
+    }
+
+    let mut tiers = HashMap::new();
+    for recipe in recipes.recipes.iter() {
+        tier_of(&recipe.result, recipes, &mut tiers);
+    }
+    tiers
+}
+
+fn animate_sorting_move(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut SortingMove, &mut Transform)>,
+) {
+    for (e, mut sorting_move, mut transform) in query.iter_mut() {
+        sorting_move.timer.tick(time.delta());
+        let progress = 1. - (1. - sorting_move.timer.percent()).powi(2);
+        let current = sorting_move.source.lerp(sorting_move.target, progress);
+        transform.translation.x = current.x;
+        transform.translation.y = current.y;
+        if sorting_move.timer.finished() {
+            commands.entity(e).remove::<SortingMove>();
+        }
+    }
+}
@@ -1,15 +1,15 @@
 use bevy::prelude::*;
 use iyes_loopless::prelude::ConditionSet;
 
-use super::backpack::BackpackInUse;
+use super::backpack::{BackpackInUse, BackpackOverflow, OverflowItem};
 use super::ItemStack;
 use crate::config::data_layout::LayoutData;
 use crate::game::backpack::Backpack;
-use crate::game::items::Item;
+use crate::game::items::{CraftItem, Item, ItemRotation};
 use crate::game::{AssetStorage, CleanupOnGameplayEnd, FallingItem, Silhouette};
 use crate::mouse::MouseInteractive;
+use crate::positioning::{CellMask, Depth, Dimens, Pos};
 use crate::positioning::{Coords, GridData};
-use crate::positioning::{Depth, Dimens, Pos};
 use crate::states::AppState;
 
 pub struct SpawnItemPlugin;
@@ -38,14 +38,25 @@ pub struct SpawnItemEvent {
     combine: bool,
     /// Which backpack this item should be put it, default to current backpack in use
     pub backpack: Option<usize>,
+    /// How many of the item the spawned entity stands for.
+    pub stack: usize,
+    /// The orientation the player gave the item. The coords must already be rotated to match.
+    pub rotation: ItemRotation,
 }
 
+/// Broadcast this to put items into a backpack. They are added to stacks of the same item that
+/// still have room first, and only take up new cells once those are full.
 #[derive(Debug)]
 pub struct StackItemEvent {
     pub item: Item,
     pub count: usize,
     /// Which backpack this item should be put it, default to current backpack in use
     pub backpack: Option<usize>,
+    /// Where new stacks fall into the backpack from. Set to None to have them appear in place.
+    pub source: Option<Vec2>,
+    /// A spot that was just emptied for this item, like that of an item the hero put on in its
+    /// place. The first new stack goes there if it fits inside it.
+    pub vacated: Option<Coords>,
 }
 
 // TODO: impl builder to simplify construction process
@@ -57,6 +68,8 @@ impl SpawnItemEvent {
             source: Some(source),
             combine,
             backpack: None,
+            stack: 1,
+            rotation: ItemRotation::default(),
         }
     }
     /// Use this for items that already exist in the backpack at the start of the game.
@@ -67,6 +80,8 @@ impl SpawnItemEvent {
             source: None,
             combine: false,
             backpack: None,
+            stack: 1,
+            rotation: ItemRotation::default(),
         }
     }
     /// Use this for items that should be spawned to specific backpack
@@ -77,8 +92,15 @@ impl SpawnItemEvent {
             source: Some(source),
             combine: false,
             backpack: Some(backpack),
+            stack: 1,
+            rotation: ItemRotation::default(),
         }
     }
+    /// Spawn a whole stack of the item instead of a single one.
+    pub fn stacked(mut self, stack: usize) -> Self {
+        self.stack = stack;
+        self
+    }
 }
 
 pub fn spawn_item(
@@ -108,7 +130,10 @@ pub fn spawn_item(
             source,
             combine,
             backpack,
+            stack,
+            rotation,
         } = evt;
+        let backpack_id = backpack.unwrap_or(default_backpack_id);
         if let Some(source) = source {
             // Spawn the animating item.
             commands
@@ -118,7 +143,7 @@ pub fn spawn_item(
                         custom_size: Some(coords.dimens.as_vec2()),
                         ..default()
                     },
-                    texture: assets.texture(&item.texture_id),
+                    texture: assets.texture(&item.texture_in(backpack_id)),
                     transform: Transform::from_xyz(source.x, source.y, Depth::FloatingItem.z()),
                     ..Default::default()
                 })
@@ -132,29 +157,32 @@ pub fn spawn_item(
                 .insert(CleanupOnGameplayEnd);
         }
         // Spawn the silhouette.
-        let backpack_id = backpack.unwrap_or(default_backpack_id);
         let mut builder = commands.spawn();
         builder
             .insert_bundle(SpriteBundle {
                 sprite: Sprite {
                     // BOGAY: this affects silhouette size, of course
-                    custom_size: Some(coords.dimens.as_vec2()),
+                    // The sprite keeps the unrotated size, the transform turns it.
+                    custom_size: Some(item.dimens.as_vec2()),
                     ..default()
                 },
-                texture: assets.texture(&item.texture_id),
+                texture: assets.texture(&item.texture_in(backpack_id)),
                 transform: Transform::from_xyz(
                     grid.offset.x + coords.pos.x as f32 + coords.dimens.x as f32 * 0.5,
                     grid.offset.y + coords.pos.y as f32 + coords.dimens.y as f32 * 0.5,
                     Depth::Item.z(),
-                ),
+                )
+                .with_rotation(rotation.as_quat()),
                 ..Default::default()
             })
-            .insert(Name::new(item.name.clone()))
+            .insert(Name::new(item.name_in(backpack_id).to_string()))
             .insert(item.clone())
             .insert(*coords)
             .insert(MouseInteractive::new(coords.dimens.as_vec2(), true))
             .insert(CleanupOnGameplayEnd)
             .insert(Backpack(backpack_id))
+            .insert(ItemStack(*stack))
+            .insert(*rotation)
             // create child to render stack count
             .with_children(|parent| {
                 let font = &crate::game::FontId::MSBold;
@@ -171,6 +199,9 @@ pub fn spawn_item(
                     ..Default::default()
                 });
             });
+        if grid.crafting.encloses(coords) {
+            builder.insert(CraftItem);
+        }
         if source.is_some() {
             builder.insert(Silhouette);
         }
@@ -206,9 +237,12 @@ pub fn animate_falling_item(
     }
 }
 
+/// Find the first spot in the inventory where an item with the given footprint fits. Pass the
+/// item's cell mask if it has one, so it can tuck into the gaps around other items.
 pub fn find_free_space<'a, I>(
     grid: &GridData,
     dimens: Dimens,
+    mask: Option<CellMask>,
     items_query: &'a I,
     same_tick_items: &[Coords], // Pass this an emtpy vec if not multiple spawn
 ) -> Option<Coords>
@@ -217,10 +251,7 @@ where
 {
     for y in 0..grid.inventory.dimens.y {
         for x in 0..grid.inventory.dimens.x {
-            let coords = Coords {
-                pos: Pos::new(x, y),
-                dimens,
-            };
+            let coords = Coords::new(Pos::new(x, y), dimens).with_mask(mask);
 
             let overlap_conflict = items_query.into_iter().any(|item| coords.overlaps(item))
                 || same_tick_items.iter().any(|item| coords.overlaps(item));
@@ -234,16 +265,13 @@ where
 }
 
 fn stack_item(
-    mut commands: Commands,
     mut events: EventReader<StackItemEvent>,
-    mut ew_spanw_item: EventWriter<SpawnItemEvent>,
-    mut items_query: Query<(Entity, &Item, &Backpack, &Coords, Option<&mut ItemStack>)>,
+    mut ew_spawn_item: EventWriter<SpawnItemEvent>,
+    mut items_query: Query<(&Item, &Backpack, &Coords, &mut ItemStack), Without<CraftItem>>,
     backpack_in_use: Query<&BackpackInUse>,
     grid: Res<GridData>,
+    mut overflow: ResMut<BackpackOverflow>,
 ) {
-    // disable item stack function
-    return;
-
     let default_backpack_id = match backpack_in_use.get_single() {
         Ok(BackpackInUse(backpack_id)) => *backpack_id,
         Err(e) => {
@@ -255,39 +283,77 @@ fn stack_item(
         }
     };
 
-    let mut new_coords_this_round: Vec<(usize, Coords)> = vec![];
+    // The new stacks are only spawned once all events are in, so they can be topped up as well.
+    let mut new_stacks: Vec<SpawnItemEvent> = vec![];
     for evt in events.iter() {
         debug!("Received {:?}", evt);
         let StackItemEvent {
             item,
             count,
             backpack,
+            source,
+            vacated,
         } = evt;
+        let mut vacated = *vacated;
         let backpack_id = backpack.unwrap_or(default_backpack_id);
-        if let Some((ent, _, _, _, item_stack)) = items_query
+        let mut left = *count;
+        for (_, _, _, mut stack) in items_query
             .iter_mut()
             // search item in target backpack
-            .find(|(_, c_item, backpack, _, _)| backpack.0 == backpack_id && c_item.id == item.id)
+            .filter(|(c_item, backpack, _, _)| backpack.0 == backpack_id && c_item.id == item.id)
         {
-            if let Some(mut item_stack) = item_stack {
-                item_stack.0 += count;
-            } else {
-                commands.entity(ent).insert(ItemStack(*count + 1));
-            }
-        } else {
-            let new_coords_in_backpack = new_coords_this_round
-                .iter()
-                .filter_map(|(c, it)| (*c == backpack_id).then(|| it.clone()));
+            let moved = left.min(stack.room(item));
+            stack.0 += moved;
+            left -= moved;
+        }
+        for new_stack in new_stacks
+            .iter_mut()
+            .filter(|evt| evt.backpack == Some(backpack_id) && evt.item.id == item.id)
+        {
+            let moved = left.min(item.max_stack.max(1).saturating_sub(new_stack.stack));
+            new_stack.stack += moved;
+            left -= moved;
+        }
+
+        for stack in item.split_into_stacks(left) {
             let curr_coords = items_query
                 .iter()
-                .filter(|(_, _, backpack, _, _)| backpack.0 == backpack_id)
-                .map(|(_, _, _, coords, _)| *coords)
-                .chain(new_coords_in_backpack)
+                .filter(|(_, backpack, _, _)| backpack.0 == backpack_id)
+                .map(|(_, _, coords, _)| *coords)
+                .chain(
+                    new_stacks
+                        .iter()
+                        .filter(|evt| evt.backpack == Some(backpack_id))
+                        .map(|evt| evt.coords),
+                )
                 .collect::<Vec<_>>();
-            let coords = find_free_space(&grid, Dimens::unit(), &vec![], &curr_coords)
-                .expect("should find a free space");
-            ew_spanw_item.send(SpawnItemEvent::without_anim(item.clone(), coords));
-            new_coords_this_round.push((backpack_id, coords));
+            let in_vacated = vacated.take().and_then(|spot| {
+                let footprint = item.footprint_at(spot.pos);
+                let fits = footprint.cells().iter().all(|cell| spot.covers(cell));
+                fits.then_some(footprint)
+            });
+            let Some(coords) = in_vacated
+                .or_else(|| find_free_space(&grid, item.dimens, item.shape, &curr_coords, &[]))
+            else {
+                overflow.items.push(OverflowItem {
+                    item: item.clone(),
+                    count: stack,
+                    backpack: backpack_id,
+                });
+                continue;
+            };
+            new_stacks.push(SpawnItemEvent {
+                item: item.clone(),
+                coords,
+                source: *source,
+                combine: false,
+                backpack: Some(backpack_id),
+                stack,
+                rotation: ItemRotation::default(),
+            });
         }
     }
+    for new_stack in new_stacks {
+        ew_spawn_item.send(new_stack);
+    }
 }
@@ -6,28 +6,43 @@ use iyes_loopless::prelude::*;
 
 use crate::audio::record_player::animate;
 use crate::audio::sound_event::SoundEvent;
-use crate::game::dungeon_sim::{init_dungeon, manage_continue_prompt, tick_timepoint};
+use crate::game::auto_use::auto_use_consumables;
+use crate::game::combat_log::{
+    draw_combat_log_window, init_combat_log, record_combat_rounds, toggle_combat_log,
+};
+use crate::game::dungeon_sim::{
+    continue_dungeon_sim, engage_reinforcements, init_dungeon, manage_continue_prompt,
+    tick_dungeon, tick_timepoint,
+};
 use crate::game::event_handling::{
-    handle_sim_loot, handle_sim_message, SimLootEvent, SimMessageEvent,
+    handle_enemy_abilities, handle_room_effects, handle_sim_loot, handle_sim_message,
+    EnemyAbilityEvent, RoomEffectEvent, SimLootEvent, SimMessageEvent,
 };
 use crate::game::feed::{handle_add_to_feed, position_feed_item, AddFeedItemEvent};
 use crate::game::item_info_system::*;
 use crate::game::timed_effect::{test_apply_modifier, tick_temporary_modifiers, TimedEffectTicker};
 use crate::game::{
-    animate_falling_item, apply_silhouette, check_drag_begin, check_drag_end,
-    check_ghost_placement_validity, combine_items_system, process_drag_event, set_ghost_position,
-    spawn_item, AlbumId, AssetStorage, CleanupOnGameplayEnd, DragEvent, ItemId, Player,
+    animate_falling_item, apply_silhouette, cancel_drag, check_drag_begin, check_drag_end,
+    check_ghost_placement_validity, combine_items_system, craft_items_system,
+    cycle_inventory_filter, process_drag_event, rotate_ghost, set_ghost_position, spawn_item,
+    AlbumId, AssetStorage, CleanupOnGameplayEnd, DragEvent, InventoryFilter, ItemId, Player,
     SpawnItemEvent, TextureId,
 };
 //use crate::hud::gold::gold_update_system;
 use crate::config::data_items::ItemsData;
-use crate::mouse::Mouse;
-use crate::positioning::{Coords, Pos};
+use crate::mouse::{Mouse, CURSOR_STAGE};
+use crate::positioning::Pos;
 use crate::AppState;
 
 use super::backpack::BackpackPlugin;
-use super::combat::{Combatant, Enemy, Hero};
+use super::combat::{CombatRoundEvent, Combatant, Enemy, Hero};
+use super::context_menu::ContextMenuPlugin;
 use super::dungeon_sim::{sync_backpack_in_use, JumpTimepointEvent};
+use super::era_view::EraViewPlugin;
+use super::grid_cursor::GridCursorPlugin;
+use super::recipe_book::RecipeBookPlugin;
+use super::sort_backpack::SortBackpackPlugin;
+use super::tech_tree::TechTreePlugin;
 use super::{
     consume_item, delete_item_system, show_item_stack_count, update_health_bar,
     update_hero_stats_display, update_label_for_combine_button, EvolutionPlugin, Eyes, Iris,
@@ -43,9 +58,13 @@ impl Plugin for GamePlugin {
             .add_event::<AddFeedItemEvent>()
             .add_event::<SimMessageEvent>()
             .add_event::<SimLootEvent>()
+            .add_event::<EnemyAbilityEvent>()
+            .add_event::<RoomEffectEvent>()
+            .add_event::<CombatRoundEvent>()
             .add_event::<JumpTimepointEvent>()
             .add_plugin(bevy_ninepatch::NinePatchPlugin::<()>::default())
             .init_resource::<Player>()
+            .init_resource::<InventoryFilter>()
             .insert_resource(TimedEffectTicker {
                 timer: Timer::new(Duration::from_secs(1), true),
             })
@@ -65,29 +84,48 @@ impl Plugin for GamePlugin {
                 ConditionSet::new()
                     .run_in_state(AppState::InGame)
                     .with_system(init_dungeon)
+                    .with_system(init_combat_log)
                     .with_system(create_initial_items)
                     //.with_system(test_slice)
                     .into(),
             )
+            .add_system_set_to_stage(
+                CURSOR_STAGE,
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(cancel_drag)
+                    .into(),
+            )
             .add_system_set(
                 ConditionSet::new()
                     .run_in_state(AppState::InGame)
                     .with_system(spawn_item)
                     .with_system(check_drag_begin)
                     .with_system(set_ghost_position)
+                    .with_system(rotate_ghost)
                     .with_system(apply_silhouette)
+                    .with_system(cycle_inventory_filter)
                     .with_system(check_ghost_placement_validity)
                     .with_system(check_drag_end)
                     .with_system(process_drag_event)
                     .with_system(combine_items_system)
+                    .with_system(craft_items_system)
                     .with_system(animate)
                     .with_system(tick_timepoint)
+                    .with_system(tick_dungeon)
+                    .with_system(continue_dungeon_sim)
                     .with_system(tick_temporary_modifiers)
                     .with_system(test_apply_modifier)
                     .with_system(handle_sim_message)
                     .with_system(handle_add_to_feed)
                     .with_system(manage_continue_prompt)
                     .with_system(handle_sim_loot)
+                    .with_system(handle_enemy_abilities)
+                    .with_system(handle_room_effects)
+                    .with_system(engage_reinforcements)
+                    .with_system(toggle_combat_log)
+                    .with_system(record_combat_rounds)
+                    .with_system(draw_combat_log_window)
                     .with_system(update_health_bar)
                     .with_system(update_hero_stats_display)
                     .with_system(eye_tracking_system)
@@ -95,6 +133,7 @@ impl Plugin for GamePlugin {
                     .with_system(update_mouse_over_item_info_style_position_system)
                     .with_system(position_feed_item)
                     .with_system(consume_item)
+                    .with_system(auto_use_consumables)
                     .with_system(delete_item_system)
                     .with_system(animate_falling_item)
                     .with_system(show_item_stack_count)
@@ -111,6 +150,12 @@ impl Plugin for GamePlugin {
                     .into(),
             )
             .add_plugin(BackpackPlugin)
+            .add_plugin(SortBackpackPlugin)
+            .add_plugin(RecipeBookPlugin)
+            .add_plugin(TechTreePlugin)
+            .add_plugin(EraViewPlugin)
+            .add_plugin(GridCursorPlugin)
+            .add_plugin(ContextMenuPlugin)
             .add_plugin(EvolutionPlugin)
             .add_plugin(WinGamePlugin)
             .add_plugin(SpawnItemPlugin);
@@ -162,18 +207,18 @@ pub fn eye_tracking_system(
     }
 }
 
+/// The items that every run starts with, and the cells of the backpack they start in.
+pub const STARTING_ITEMS: &[(ItemId, i32, i32)] = &[
+    (ItemId::GatheringAndHunting, 5, 3),
+    (ItemId::Fishery, 4, 3),
+    (ItemId::StoneTool, 4, 2),
+];
+
 pub fn create_initial_items(mut spawn: EventWriter<SpawnItemEvent>, items_db: Res<ItemsData>) {
-    let spawn_datas = vec![
-        (ItemId::GatheringAndHunting, 5, 3),
-        (ItemId::Fishery, 4, 3),
-        (ItemId::StoneTool, 4, 2),
-    ];
-    for (id, x, y) in spawn_datas {
-        let item = items_db.try_get_item(id).unwrap();
-        spawn.send(SpawnItemEvent::without_anim(
-            item.1,
-            Coords::new(Pos::new(x, y), item.0),
-        ));
+    for (id, x, y) in STARTING_ITEMS.iter().cloned() {
+        let (_, item) = items_db.try_get_item(id).unwrap();
+        let coords = item.footprint_at(Pos::new(x, y));
+        spawn.send(SpawnItemEvent::without_anim(item, coords));
     }
 }
 
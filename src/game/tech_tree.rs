@@ -0,0 +1,243 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::{AppLooplessStateExt, ConditionSet};
+
+use crate::config::config_input::{InputAction, InputConfig};
+use crate::config::data_items::ItemsData;
+use crate::game::backpack::BackpackInUse;
+use crate::game::{EvolutionRule, Item, ItemId, EVOLUTION_RULES};
+use crate::states::AppState;
+
+/// Size of a node in the tech tree, in points.
+const NODE_SIZE: egui::Vec2 = egui::Vec2::new(120., 28.);
+/// Space between the columns and rows of the tech tree, in points.
+const NODE_GAP: egui::Vec2 = egui::Vec2::new(60., 12.);
+
+pub struct TechTreePlugin;
+
+impl Plugin for TechTreePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TechTree>()
+            .add_enter_system(AppState::InGame, reset_reached_items)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(AppState::InGame)
+                    .with_system(toggle_tech_tree)
+                    .with_system(record_reached_items)
+                    .with_system(draw_tech_tree_window)
+                    .into(),
+            );
+    }
+}
+
+/// Resource. Which items of the evolution chain the current run has had so far.
+#[derive(Default)]
+pub struct TechTree {
+    pub reached: HashSet<ItemId>,
+    /// Whether the tech tree window is open.
+    pub show_window: bool,
+}
+
+fn reset_reached_items(mut tech_tree: ResMut<TechTree>) {
+    tech_tree.reached.clear();
+}
+
+fn record_reached_items(mut tech_tree: ResMut<TechTree>, new_items: Query<&Item, Added<Item>>) {
+    for item in new_items.iter() {
+        tech_tree.reached.insert(item.id.clone());
+    }
+}
+
+/// The OpenTechTree binding (`U` by default) opens and closes the tech tree.
+fn toggle_tech_tree(
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut tech_tree: ResMut<TechTree>,
+) {
+    if config.just_pressed(InputAction::OpenTechTree, &keys, &buttons, &pads) {
+        tech_tree.show_window = !tech_tree.show_window;
+    }
+}
+
+/// The column of each rule: one further right than the furthest item that unlocks it.
+fn columns(rules: &[EvolutionRule]) -> HashMap<ItemId, usize> {
+    let mut columns = rules
+        .iter()
+        .map(|rule| (rule.item.clone(), 0))
+        .collect::<HashMap<_, _>>();
+    // Each pass settles at least one more step of the longest chain, a cycle is cut off after
+    // as many passes as there are rules.
+    for _ in 0..rules.len() {
+        let mut changed = false;
+        for rule in rules {
+            let column = rule
+                .unlocked_by
+                .iter()
+                .filter_map(|(id, _)| columns.get(id).map(|column| column + 1))
+                .max()
+                .unwrap_or(0);
+            if columns[&rule.item] < column {
+                columns.insert(rule.item.clone(), column);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    columns
+}
+
+/// Draws every item of the evolution chain in columns, left to right in the order they can be
+/// unlocked. Solid edges lead from the items that unlock a node, dashed ones from the items it grows
+/// with. Items that the current run has had are green.
+fn draw_tech_tree_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut tech_tree: ResMut<TechTree>,
+    items_data: Res<ItemsData>,
+    backpack_in_use: Query<&BackpackInUse>,
+) {
+    if !tech_tree.show_window {
+        return;
+    }
+    let era = backpack_in_use
+        .get_single()
+        .map(|BackpackInUse(id)| *id)
+        .unwrap_or_default();
+    let names = items_data
+        .items
+        .iter()
+        .map(|(_, item)| (item.id.clone(), item.name_in(era).to_string()))
+        .collect::<HashMap<_, _>>();
+    let name = |id: &ItemId| {
+        names
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| format!("{:?}", id))
+    };
+
+    let columns = columns(EVOLUTION_RULES);
+    let mut rows = HashMap::<usize, usize>::new();
+    let mut cells = HashMap::new();
+    for rule in EVOLUTION_RULES {
+        let column = columns[&rule.item];
+        let row = rows.entry(column).or_default();
+        cells.insert(rule.item.clone(), (column, *row));
+        *row += 1;
+    }
+    let size = egui::vec2(
+        (rows.len() as f32) * (NODE_SIZE.x + NODE_GAP.x),
+        (rows.values().max().copied().unwrap_or(0) as f32) * (NODE_SIZE.y + NODE_GAP.y),
+    );
+
+    let TechTree {
+        reached,
+        show_window,
+    } = &mut *tech_tree;
+    egui::Window::new("Tech tree")
+        .open(show_window)
+        .default_width(640.)
+        .default_height(480.)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(
+                "Solid lines lead from the items that unlock an item, dashed lines from the items \
+                 it grows with. Green items have turned up this run.",
+            );
+            ui.separator();
+            egui::ScrollArea::both().show(ui, |ui| {
+                let (area, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                let node_rect = |id: &ItemId| {
+                    cells.get(id).map(|(column, row)| {
+                        egui::Rect::from_min_size(
+                            area.min
+                                + egui::vec2(
+                                    *column as f32 * (NODE_SIZE.x + NODE_GAP.x),
+                                    *row as f32 * (NODE_SIZE.y + NODE_GAP.y),
+                                ),
+                            NODE_SIZE,
+                        )
+                    })
+                };
+                let painter = ui.painter_at(area);
+
+                for rule in EVOLUTION_RULES {
+                    let Some(to) = node_rect(&rule.item) else {
+                        continue;
+                    };
+                    for (id, _) in rule.unlocked_by {
+                        if let Some(from) = node_rect(id) {
+                            painter.line_segment(
+                                [from.right_center(), to.left_center()],
+                                egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY),
+                            );
+                        }
+                    }
+                    for id in rule.growth_sources(&items_data).iter() {
+                        if let Some(from) = node_rect(id) {
+                            painter.extend(egui::Shape::dashed_line(
+                                &[from.right_center(), to.left_center()],
+                                egui::Stroke::new(1., egui::Color32::from_rgb(200, 160, 60)),
+                                4.,
+                                4.,
+                            ));
+                        }
+                    }
+                }
+
+                for rule in EVOLUTION_RULES {
+                    let Some(rect) = node_rect(&rule.item) else {
+                        continue;
+                    };
+                    let fill = if reached.contains(&rule.item) {
+                        egui::Color32::from_rgb(40, 110, 50)
+                    } else {
+                        egui::Color32::from_gray(50)
+                    };
+                    painter.rect_filled(rect, 4., fill);
+                    painter.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        name(&rule.item),
+                        egui::FontId::proportional(13.),
+                        egui::Color32::WHITE,
+                    );
+                    ui.interact(rect, ui.id().with(&rule.item), egui::Sense::hover())
+                        .on_hover_text(describe_rule(rule, &items_data, &name));
+                }
+            });
+        });
+}
+
+/// What it takes to unlock and grow an item, for the tooltip of its node.
+fn describe_rule(
+    rule: &EvolutionRule,
+    items_data: &ItemsData,
+    name: &dyn Fn(&ItemId) -> String,
+) -> String {
+    let mut lines = vec![];
+    if !rule.unlockable {
+        lines.push("Only comes from the starting backpack.".to_string());
+    } else if rule.unlocked_by.is_empty() {
+        lines.push("Needs nothing to unlock.".to_string());
+    } else {
+        let needs = rule
+            .unlocked_by
+            .iter()
+            .map(|(id, count)| format!("{} {}", count, name(id)))
+            .collect::<Vec<_>>();
+        lines.push(format!("Unlocked by: {}", needs.join(", ")));
+    }
+    if rule.population > 0 {
+        lines.push(format!("Needs a population over {}", rule.population));
+    }
+    let sources = rule.growth_sources(items_data);
+    if !sources.is_empty() {
+        let grows = sources.iter().map(name).collect::<Vec<_>>();
+        lines.push(format!("Grows with: {}", grows.join(", ")));
+    }
+    lines.join("\n")
+}
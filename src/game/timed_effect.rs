@@ -1,5 +1,6 @@
-﻿use crate::game::combat::{Enemy, Hero};
-use crate::{default, Entity, KeyCode, Query, Res};
+﻿use crate::config::config_input::{InputAction, InputConfig};
+use crate::game::combat::{Enemy, Hero};
+use crate::{default, Entity, GamepadButton, KeyCode, MouseButton, Query, Res};
 use bevy::input::Input;
 use bevy::prelude::{Commands, Component, ResMut};
 use bevy::time::{Time, Timer};
@@ -118,12 +119,28 @@ pub fn tick_temporary_modifiers(
     }
 }
 
+/// Drop the modifiers on the enemy without reverting them. Call this whenever the enemy is replaced,
+/// or they get reverted against the stats of the next one once they expire.
+pub fn clear_enemy_modifiers(modifiers: &Query<(Entity, &TemporaryModifier)>, cmd: &mut Commands) {
+    for (e, modifier) in modifiers.iter() {
+        if !modifier.on_hero {
+            cmd.entity(e).despawn();
+        }
+    }
+}
+
 pub fn apply_timed_modifier(modifier: TemporaryModifier, cmd: &mut Commands) {
     cmd.spawn().insert(modifier);
 }
 
-pub fn test_apply_modifier(input: Res<Input<KeyCode>>, mut cmd: Commands) {
-    if input.just_pressed(KeyCode::M) {
+pub fn test_apply_modifier(
+    config: Res<InputConfig>,
+    input: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
+    mut cmd: Commands,
+) {
+    if config.just_pressed(InputAction::TestBuffHero, &input, &buttons, &pads) {
         apply_timed_modifier(
             TemporaryModifier {
                 time: 10.0,
@@ -135,7 +152,7 @@ pub fn test_apply_modifier(input: Res<Input<KeyCode>>, mut cmd: Commands) {
         );
     }
 
-    if input.just_pressed(KeyCode::N) {
+    if config.just_pressed(InputAction::TestDebuffEnemy, &input, &buttons, &pads) {
         apply_timed_modifier(
             TemporaryModifier {
                 time: 10.0,
@@ -1,7 +1,7 @@
 use super::backpack::Backpack;
-use crate::game::items::Item;
+use crate::game::count_by_tag;
+use crate::game::items::{stack_count, Item, ItemStack, ItemTag};
 use crate::game::GameResult;
-use crate::game::ItemId;
 use crate::positioning::Coords;
 use crate::states::AppState;
 use bevy::prelude::*;
@@ -21,34 +21,22 @@ impl Plugin for WinGamePlugin {
     }
 }
 
-fn contains<'a>(items: impl IntoIterator<Item = &'a &'a Item>, id: ItemId) -> bool {
-    items.into_iter().any(|it| it.id == id)
-}
-
 fn win_game(
-    items: Query<(Entity, &Item, &Backpack, &Coords)>,
+    items: Query<(Entity, &Item, &Backpack, &Coords, Option<&ItemStack>)>,
     mut cmd: Commands,
     mut victory: ResMut<State<GameResult>>,
 ) {
     let now_items = items
         .iter()
-        .filter(|(_, _, backpack, _)| backpack.0 == 400)
-        .map(|(_, item, _, _)| item)
+        .filter(|(_, _, backpack, _, _)| backpack.0 == 400)
+        .map(|(_, item, _, _, stack)| (item, stack_count(stack)))
         .collect::<Vec<_>>();
 
-    let win_conds = [
-        ItemId::Theocracy,
-        ItemId::PermanentMember,
-        ItemId::Empire,
-        ItemId::Totalitarian,
-    ];
-    for win_cond in win_conds {
-        if contains(&now_items, win_cond) {
-            if victory.current().clone() == GameResult::Lost {
-                victory.set(GameResult::Won).unwrap();
-            }
-            info!("Win!");
-            cmd.insert_resource(NextState(AppState::GameEnded));
+    if count_by_tag(&now_items, ItemTag::Victory) > 0 {
+        if victory.current().clone() == GameResult::Lost {
+            victory.set(GameResult::Won).unwrap();
         }
+        info!("Win!");
+        cmd.insert_resource(NextState(AppState::GameEnded));
     }
 }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bevy::asset::LoadState;
 use bevy::prelude::*;
@@ -6,6 +6,7 @@ use iyes_loopless::prelude::NextState;
 
 use crate::config::config_audio::AudioConfig;
 use crate::config::config_debug::DebugConfig;
+use crate::config::config_input::InputConfig;
 use crate::config::config_sim::SimConfig;
 use crate::config::data_blueprint::BlueprintData;
 use crate::config::data_enemies::EnemiesData;
@@ -18,15 +19,26 @@ use crate::loading::loading_instructions::prepare_loading_config;
 use crate::AppState;
 
 pub fn load_configs(server: Res<AssetServer>, mut assets: ResMut<AssetStorage>) {
-    assets.audio = server.load("config/default/config.audio.ron");
-    assets.debug = server.load("config/default/config.debug.ron");
-    assets.sim = server.load("config/default/config.sim.ron");
-    assets.blueprint = server.load("config/default/data.blueprint.ron");
-    assets.enemies = server.load("config/default/data.enemies.ron");
-    assets.items = server.load("config/default/data.items.ron");
-    assets.layout = server.load("config/default/data.layout.ron");
-    assets.recipes = server.load("config/default/data.recipes.ron");
-    assets.texts = server.load("config/default/data.texts.ron");
+    assets.audio = server.load(&config_path("config.audio.ron"));
+    assets.debug = server.load(&config_path("config.debug.ron"));
+    assets.input = server.load(&config_path("config.input.ron"));
+    assets.sim = server.load(&config_path("config.sim.ron"));
+    assets.blueprint = server.load(&config_path("data.blueprint.ron"));
+    assets.enemies = server.load(&config_path("data.enemies.ron"));
+    assets.items = server.load(&config_path("data.items.ron"));
+    assets.layout = server.load(&config_path("data.layout.ron"));
+    assets.recipes = server.load(&config_path("data.recipes.ron"));
+    assets.texts = server.load(&config_path("data.texts.ron"));
+}
+
+/// The config file from the override directory if it's there, or else from the default directory.
+fn config_path(file_name: &str) -> String {
+    let override_path = format!("config/override/{}", file_name);
+    if Path::new("assets").join(&override_path).exists() {
+        override_path
+    } else {
+        format!("config/default/{}", file_name)
+    }
 }
 
 pub fn load_assets(
@@ -111,6 +123,7 @@ pub fn add_configs(
     assets: Res<AssetStorage>,
     audio: Res<Assets<AudioConfig>>,
     debug: Res<Assets<DebugConfig>>,
+    input: Res<Assets<InputConfig>>,
     sim: Res<Assets<SimConfig>>,
     blueprint: Res<Assets<BlueprintData>>,
     enemies: Res<Assets<EnemiesData>>,
@@ -131,6 +144,12 @@ pub fn add_configs(
             .cloned()
             .expect("debug.ron wasn't loaded (yet)!"),
     );
+    commands.insert_resource(
+        input
+            .get(&assets.input)
+            .cloned()
+            .expect("input.ron wasn't loaded (yet)!"),
+    );
     commands.insert_resource(
         sim.get(&assets.sim)
             .cloned()
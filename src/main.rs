@@ -15,6 +15,7 @@ use opening::OpeningPlugin;
 use crate::audio::plugin::MyAudioPlugin;
 use crate::config::config_audio::{AudioConfig, AudioConfigLoader};
 use crate::config::config_debug::{DebugConfig, DebugConfigLoader};
+use crate::config::config_input::{InputConfig, InputConfigLoader};
 use crate::config::config_sim::{SimConfig, SimConfigLoader};
 use crate::config::data_blueprint::{BlueprintData, BlueprintDataLoader};
 use crate::config::data_enemies::{EnemiesData, EnemiesDataLoader};
@@ -22,6 +23,7 @@ use crate::config::data_items::{ItemsData, ItemsDataLoader};
 use crate::config::data_layout::{LayoutData, LayoutDataLoader};
 use crate::config::data_recipes::{RecipesData, RecipesDataLoader};
 use crate::config::data_texts::{TextsData, TextsDataLoader};
+use crate::controls::ControlsPlugin;
 use crate::game::camera::set_cam_scale;
 use crate::game::GamePlugin;
 use crate::game_ended::GameEndedPlugin;
@@ -34,7 +36,9 @@ use crate::window_event_handler::handle_window;
 
 pub mod animation;
 mod audio;
+mod cli;
 mod config;
+mod controls;
 pub mod game;
 mod game_ended;
 mod hud;
@@ -51,6 +55,10 @@ mod window_event_handler;
 pub const GAME_NAME: &str = "時光巫師 3";
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(exit_code) = cli::run(&args) {
+        std::process::exit(exit_code);
+    }
     App::new().insert_resource(bevy::log::LogSettings {
         filter: "info,wgpu=error,symphonia_core=warn,symphonia_format_ogg=warn,symphonia_codec_vorbis=warn,symphonia_bundle_mp3=warn,bag_goblin=info".to_string(),
         level: Level::TRACE,
@@ -71,6 +79,7 @@ fn main() {
         .add_plugin(EguiPlugin)
         .add_plugin(MyAudioPlugin)
         .add_plugin(MousePlugin)
+        .add_plugin(ControlsPlugin)
         .add_plugin(LoadingPlugin)
         .add_plugin(OpeningPlugin)
         .add_plugin(MainMenuPlugin)
@@ -81,6 +90,8 @@ fn main() {
         .init_asset_loader::<AudioConfigLoader>()
         .add_asset::<DebugConfig>()
         .init_asset_loader::<DebugConfigLoader>()
+        .add_asset::<InputConfig>()
+        .init_asset_loader::<InputConfigLoader>()
         .add_asset::<SimConfig>()
         .init_asset_loader::<SimConfigLoader>()
         .add_asset::<BlueprintData>()
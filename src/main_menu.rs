@@ -7,7 +7,10 @@ use crate::audio::sound_event::SoundEvent;
 use crate::config::data_layout::LayoutData;
 use crate::game::create_widget_backpack::create_layout_background;
 use crate::game::create_widget_feed::create_layout_feed;
-use crate::game::create_widget_grids::{create_layout_combine_button, create_layout_grids};
+use crate::game::create_widget_grids::{
+    create_layout_combine_button, create_layout_craft_button, create_layout_grids,
+    create_layout_recipe_book_button, create_layout_sort_button,
+};
 use crate::game::create_widget_hero::create_layout_hero;
 use crate::game::create_widget_instruction::create_layout_instruction;
 use crate::game::create_widget_music::create_layout_music;
@@ -38,6 +41,9 @@ impl Plugin for MainMenuPlugin {
                     .with_system(create_layout_grids)
                     .with_system(create_layout_toasts)
                     .with_system(create_layout_combine_button)
+                    .with_system(create_layout_sort_button)
+                    .with_system(create_layout_craft_button)
+                    .with_system(create_layout_recipe_book_button)
                     .with_system(create_layout_instruction)
                     //.with_system(create_layout_hero) /* state gui */
                     .with_system(init_menu) /* change this if need to modify background title */
@@ -1,7 +1,9 @@
 use bevy::prelude::*;
 use iyes_loopless::prelude::ConditionSet;
 
+use crate::config::config_input::{InputAction, InputConfig};
 use crate::game::camera::GameCamera;
+use crate::positioning::{Coords, GridData, Pos};
 use crate::Update;
 
 #[derive(Default)]
@@ -15,11 +17,25 @@ pub struct Mouse {
     /// Whether or not the mouse is currently in a dragging operation.
     ///
     /// It is handy to store this separately, rather than relying on whether or not the LMB is
-    /// held down, because this way we could add an accessibility mode that
-    /// allows click-to-start-dragging, click-to-stop-dragging.
+    /// held down, because this way the `click_to_drag` setting in `config.input.ron` can allow
+    /// click-to-start-dragging, click-to-stop-dragging.
     pub is_dragging: bool,
     pub disabled: bool,
     pub out_of_bounds: bool,
+    /// Clicks that the grid cursor makes this frame on behalf of the keyboard or a gamepad.
+    pub cursor_actions: CursorActions,
+}
+
+/// The mouse actions that the grid cursor can stand in for. They apply to whatever is under
+/// `Mouse.position`, which the grid cursor moves while it is in use.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct CursorActions {
+    /// Same as a left click. Picks an item up, or puts down the item that was picked up.
+    pub click: bool,
+    /// Same as a SHIFT-click.
+    pub use_item: bool,
+    /// Same as a CTRL-ALT-click.
+    pub delete: bool,
 }
 
 /// Any entity that that be interacted with by the mouse.
@@ -35,8 +51,15 @@ pub struct MouseInteractive {
     /// Whether the mouse just clicked this entity.
     /// (Set to true upon signal going up, aka the LMB going down.)
     pub clicked: bool,
+    /// Clicked with the UseItem binding held down (LShift by default).
     pub shift_clicked: bool,
+    /// Clicked with the SplitStack binding but not the DeleteItem binding held down (LCtrl but
+    /// not LAlt by default).
+    pub ctrl_clicked: bool,
+    /// Clicked with the DeleteItem binding held down (LCtrl + LAlt by default).
     pub ctrl_alt_clicked: bool,
+    /// The OpenContextMenu binding (a right-click by default) just went down over this entity.
+    pub right_clicked: bool,
 }
 
 impl MouseInteractive {
@@ -47,18 +70,26 @@ impl MouseInteractive {
             hovered: false,
             clicked: false,
             shift_clicked: false,
+            ctrl_clicked: false,
             ctrl_alt_clicked: false,
+            right_clicked: false,
         }
     }
 }
 
+/// Systems that stand in for the mouse, or that have to see a key press before the rest of the game
+/// does, go in this stage. It runs after the mouse position has been read, and before anything
+/// looks at what the mouse is hovering over.
+pub const CURSOR_STAGE: &str = "mouse_cursor";
+
 pub struct MousePlugin;
 
 impl Plugin for MousePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Mouse>()
             .add_stage_before(Update, "mouse_2", SystemStage::parallel())
-            .add_stage_before("mouse_2", "mouse_1", SystemStage::parallel())
+            .add_stage_before("mouse_2", CURSOR_STAGE, SystemStage::parallel())
+            .add_stage_before(CURSOR_STAGE, "mouse_1", SystemStage::parallel())
             .add_system_set_to_stage(
                 "mouse_1",
                 ConditionSet::new().with_system(calc_mouse_pos).into(),
@@ -86,6 +117,7 @@ pub fn calc_mouse_pos(
     mut mouse: ResMut<Mouse>,
     query_cam: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
 ) {
+    mouse.cursor_actions = CursorActions::default();
     if let Ok((camera, camera_transform)) = query_cam.get_single() {
         // Bevy will not return anything here if the mouse is out of screen bounds...
         // ... unless a mouse button is pressed, for whatever reason.
@@ -116,26 +148,48 @@ pub fn calc_mouse_pos(
 pub fn track_mouse_hover(
     input: Res<Input<MouseButton>>,
     keys: Res<Input<KeyCode>>,
+    pads: Res<Input<GamepadButton>>,
+    config: Res<InputConfig>,
     mouse: Res<Mouse>,
-    mut query: Query<(&mut MouseInteractive, &GlobalTransform, &Visibility)>,
+    grid: Option<Res<GridData>>,
+    mut query: Query<(
+        &mut MouseInteractive,
+        &GlobalTransform,
+        &Visibility,
+        Option<&Coords>,
+    )>,
 ) {
     let (highest_z, nr_items) = query.iter_mut().fold(
         (-1000. as f32, 0),
-        |(highest_z, nr_items), (mut interactive, transform, visibility)| {
+        |(highest_z, nr_items), (mut interactive, transform, visibility, coords)| {
+            // Shaped items can sit in each other's gaps, so only the cells an item covers count.
+            let covers_mouse = match (coords, &grid) {
+                (Some(coords), Some(grid)) => {
+                    coords.covers(&Pos::from(mouse.position - grid.offset))
+                }
+                _ => true,
+            };
             interactive.hovered = !mouse.disabled
                 && visibility.is_visible
                 && mouse.position.x > transform.translation().x - interactive.size.x * 0.5
                 && mouse.position.x < transform.translation().x + interactive.size.x * 0.5
                 && mouse.position.y > transform.translation().y - interactive.size.y * 0.5
-                && mouse.position.y < transform.translation().y + interactive.size.y * 0.5;
-            interactive.clicked = interactive.hovered && input.just_pressed(MouseButton::Left);
+                && mouse.position.y < transform.translation().y + interactive.size.y * 0.5
+                && covers_mouse;
+            let cursor = mouse.cursor_actions;
+            let left_pressed = input.just_pressed(MouseButton::Left);
+            interactive.clicked = interactive.hovered
+                && (left_pressed || cursor.click || cursor.use_item || cursor.delete);
+            let held = |action| config.pressed(action, &keys, &input, &pads);
+            let deleting = held(InputAction::DeleteItem);
             interactive.shift_clicked = interactive.hovered
-                && input.just_pressed(MouseButton::Left)
-                && keys.pressed(KeyCode::LShift);
-            interactive.ctrl_alt_clicked = interactive.hovered
-                && input.just_pressed(MouseButton::Left)
-                && keys.pressed(KeyCode::LControl)
-                && keys.pressed(KeyCode::LAlt);
+                && ((left_pressed && held(InputAction::UseItem)) || cursor.use_item);
+            interactive.ctrl_clicked =
+                interactive.hovered && left_pressed && held(InputAction::SplitStack) && !deleting;
+            interactive.ctrl_alt_clicked =
+                interactive.hovered && ((left_pressed && deleting) || cursor.delete);
+            interactive.right_clicked = interactive.hovered
+                && config.just_pressed(InputAction::OpenContextMenu, &keys, &input, &pads);
             if interactive.hovered {
                 (highest_z.max(transform.translation().z), nr_items + 1)
             } else {
@@ -151,13 +205,15 @@ pub fn track_mouse_hover(
         );
         query
             .iter_mut()
-            .filter(|(interactive, transform, _)| {
+            .filter(|(interactive, transform, _, _)| {
                 interactive.hovered && (transform.translation().z - highest_z).abs() > f32::EPSILON
             })
-            .for_each(|(mut interactive, transform, _)| {
+            .for_each(|(mut interactive, transform, _, _)| {
                 trace!("Culling {:?}", transform.translation().z);
                 interactive.hovered = false;
                 interactive.clicked = false;
+                interactive.ctrl_clicked = false;
+                interactive.right_clicked = false;
             });
     }
 }
@@ -1,4 +1,5 @@
 use crate::audio::sound_event::SoundEvent;
+use crate::config::config_input::{InputAction, InputConfig};
 use crate::config::data_layout::LayoutData;
 use crate::game::assets::{AlbumId, FontId, SoundId};
 use crate::game::{AssetStorage, CleanupOnGameplayEnd, TextureId};
@@ -146,7 +147,10 @@ pub fn opening(
     mut txt: Query<(&mut Text, &Subtitle), With<Subtitle>>,
     mut audio: EventWriter<SoundEvent>,
     assets: Res<AssetStorage>,
-    input: Res<Input<KeyCode>>,
+    config: Res<InputConfig>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    pads: Res<Input<GamepadButton>>,
 ) {
     trace!("opening");
 
@@ -192,7 +196,8 @@ pub fn opening(
         }
     }
 
-    let skip = cfg!(debug_assertions) && input.just_pressed(KeyCode::Back);
+    let skip = cfg!(debug_assertions)
+        && config.just_pressed(InputAction::SkipOpening, &keys, &buttons, &pads);
     if skip || now_play.0 > 2 {
         audio.send(SoundEvent::KillAllMusic);
         audio.send(SoundEvent::KillAllSoundEffects);
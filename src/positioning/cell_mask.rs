@@ -0,0 +1,186 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::positioning::{Dimens, Pos};
+
+/// The largest footprint that a mask can describe, in either direction.
+pub const MAX_MASK_SIZE: i32 = 8;
+
+/// Describes which cells inside an entity's bounding box are actually part of its body, so that
+/// items can have any polyomino shape. One bit per cell, counted row by row from the bottom-left.
+///
+/// In the data files a mask is written as a list of rows, top row first, where `#` marks a filled
+/// cell and `.` an empty one:
+/// ```ron
+/// shape: [
+///     "##",
+///     "#.",
+/// ],
+/// ```
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct CellMask {
+    bits: u64,
+    dimens: Dimens,
+}
+
+impl CellMask {
+    /// A mask in which every cell of the given dimensions is filled.
+    #[must_use]
+    pub fn full(dimens: Dimens) -> Self {
+        let mut mask = CellMask { bits: 0, dimens };
+        for y in 0..dimens.y {
+            for x in 0..dimens.x {
+                mask.set(x, y);
+            }
+        }
+        mask
+    }
+
+    pub fn dimens(&self) -> Dimens {
+        self.dimens
+    }
+
+    /// Returns true iff the cell at the given position, relative to the bottom-left corner, is
+    /// filled. Positions outside of the mask are never filled.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.dimens.x
+            && y < self.dimens.y
+            && self.bits & (1u64 << (y * MAX_MASK_SIZE + x)) != 0
+    }
+
+    /// All filled cells, relative to the bottom-left corner.
+    pub fn cells(&self) -> impl Iterator<Item = Pos> + '_ {
+        (0..self.dimens.y).flat_map(move |y| {
+            (0..self.dimens.x)
+                .filter(move |x| self.contains(*x, y))
+                .map(move |x| Pos::new(x, y))
+        })
+    }
+
+    /// The same shape turned a quarter clockwise. The width and height swap places.
+    #[must_use]
+    pub fn rotated(&self) -> Self {
+        let mut rotated = CellMask {
+            bits: 0,
+            dimens: Dimens::new(self.dimens.y, self.dimens.x),
+        };
+        for cell in self.cells() {
+            rotated.set(cell.y, self.dimens.x - 1 - cell.x);
+        }
+        rotated
+    }
+
+    fn set(&mut self, x: i32, y: i32) {
+        self.bits |= 1u64 << (y * MAX_MASK_SIZE + x);
+    }
+}
+
+impl TryFrom<Vec<String>> for CellMask {
+    type Error = String;
+
+    fn try_from(rows: Vec<String>) -> Result<Self, Self::Error> {
+        let height = rows.len() as i32;
+        let width = rows.first().map(|row| row.chars().count()).unwrap_or(0) as i32;
+        if width == 0 || width > MAX_MASK_SIZE || height > MAX_MASK_SIZE {
+            return Err(format!(
+                "A cell mask must be between 1 and {} cells wide and high.",
+                MAX_MASK_SIZE
+            ));
+        }
+        let mut mask = CellMask {
+            bits: 0,
+            dimens: Dimens::new(width, height),
+        };
+        for (row_idx, row) in rows.iter().enumerate() {
+            if row.chars().count() as i32 != width {
+                return Err("All rows of a cell mask must be equally long.".to_string());
+            }
+            // The top row comes first, but y counts up from the bottom.
+            let y = height - 1 - row_idx as i32;
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => mask.set(x as i32, y),
+                    '.' => {}
+                    _ => return Err(format!("Unexpected character '{}' in cell mask.", c)),
+                }
+            }
+        }
+        if mask.bits == 0 {
+            return Err("A cell mask must have at least one filled cell.".to_string());
+        }
+        Ok(mask)
+    }
+}
+
+impl From<CellMask> for Vec<String> {
+    fn from(mask: CellMask) -> Self {
+        (0..mask.dimens.y)
+            .rev()
+            .map(|y| {
+                (0..mask.dimens.x)
+                    .map(|x| if mask.contains(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(rows: &[&str]) -> Result<CellMask, String> {
+        CellMask::try_from(rows.iter().map(|row| row.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_rows_top_first() {
+        let l = mask(&["#.", "##"]).unwrap();
+        assert_eq!(l.dimens(), Dimens::new(2, 2));
+        assert!(l.contains(0, 0));
+        assert!(l.contains(1, 0));
+        assert!(l.contains(0, 1));
+        assert!(!l.contains(1, 1));
+        assert!(!l.contains(-1, 0));
+        assert!(!l.contains(2, 0));
+    }
+
+    #[test]
+    fn rejects_bad_masks() {
+        assert!(mask(&[]).is_err());
+        assert!(mask(&["..", ".."]).is_err());
+        assert!(mask(&["##", "#"]).is_err());
+        assert!(mask(&["#x"]).is_err());
+        assert!(mask(&["#########"]).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_rows() {
+        let rows = vec!["##.".to_string(), ".##".to_string()];
+        let parsed = CellMask::try_from(rows.clone()).unwrap();
+        assert_eq!(Vec::<String>::from(parsed), rows);
+    }
+
+    #[test]
+    fn rotates_a_quarter_clockwise() {
+        let l = mask(&["#.", "#.", "##"]).unwrap();
+        let rotated = l.rotated();
+        assert_eq!(
+            Vec::<String>::from(rotated),
+            vec!["###".to_string(), "#..".to_string()]
+        );
+        assert_eq!(rotated.cells().count(), l.cells().count());
+        assert_eq!(rotated.rotated().rotated().rotated(), l);
+    }
+
+    #[test]
+    fn full_fills_every_cell() {
+        let full = CellMask::full(Dimens::new(3, 2));
+        assert_eq!(full.cells().count(), 6);
+        assert_eq!(full, mask(&["###", "###"]).unwrap());
+    }
+}
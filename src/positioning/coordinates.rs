@@ -4,7 +4,7 @@ use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::positioning::position::Pos;
-use crate::positioning::Dimens;
+use crate::positioning::{CellMask, Dimens};
 
 /// Can be used as a component for entities that are on the grid.
 /// Contains a discrete position and dimensions.
@@ -20,19 +20,55 @@ pub struct Coords {
     pub pos: Pos,
     /// Width and height of the entity.
     pub dimens: Dimens,
+    /// The cells within the width and height that the entity actually covers.
+    /// If None, the entity is a solid rectangle.
+    #[serde(default)]
+    pub mask: Option<CellMask>,
 }
 
 impl Coords {
     pub fn new(pos: Pos, dimens: Dimens) -> Self {
-        Coords { pos, dimens }
+        Coords {
+            pos,
+            dimens,
+            mask: None,
+        }
     }
 
-    /// Return true iff the two Coords rectangles overlap at all.
+    /// Give the entity a non-rectangular shape. The mask should have the same dimensions.
+    #[must_use]
+    pub fn with_mask(mut self, mask: Option<CellMask>) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Return true iff the two Coords overlap at all. Takes the shape of masked entities into
+    /// account, so two L-shaped items can interlock.
     pub fn overlaps(&self, other: &Coords) -> bool {
-        self.pos.x < other.pos.x + other.dimens.x
+        let rects_overlap = self.pos.x < other.pos.x + other.dimens.x
             && self.pos.x + self.dimens.x > other.pos.x
             && self.pos.y < other.pos.y + other.dimens.y
-            && self.pos.y + self.dimens.y > other.pos.y
+            && self.pos.y + self.dimens.y > other.pos.y;
+        if !rects_overlap || (self.mask.is_none() && other.mask.is_none()) {
+            return rects_overlap;
+        }
+        self.cells().any(|cell| other.covers(&cell))
+    }
+
+    /// Return true iff the entity covers the cell at the given absolute position.
+    pub fn covers(&self, pos: &Pos) -> bool {
+        let x = pos.x - self.pos.x;
+        let y = pos.y - self.pos.y;
+        match self.mask {
+            Some(mask) => mask.contains(x, y),
+            None => x >= 0 && y >= 0 && x < self.dimens.x && y < self.dimens.y,
+        }
+    }
+
+    /// The absolute positions of all cells that the entity covers.
+    pub fn cells(&self) -> Vec<Pos> {
+        let mask = self.mask.unwrap_or_else(|| CellMask::full(self.dimens));
+        mask.cells().map(|cell| cell + self.pos).collect()
     }
     pub fn overlaps_pos(&self, other: &Pos) -> bool {
         let other = Coords::new(*other, Dimens::new(1, 1));
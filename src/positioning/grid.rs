@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 
-use crate::positioning::Coords;
+use crate::game::EquipmentSlot;
+use crate::positioning::{Coords, Dimens, Pos};
 
 pub struct GridData {
     /// The translation offset of the grid.
@@ -8,6 +11,8 @@ pub struct GridData {
     pub offset: Vec2,
     pub inventory: Coords,
     pub crafting: Coords,
+    /// The slots on the hero widget that equipped items go in.
+    pub equipment: HashMap<EquipmentSlot, Coords>,
 }
 
 impl GridData {
@@ -23,4 +28,21 @@ impl GridData {
     pub fn center_crafting(&self) -> Vec2 {
         Vec2::new(self.calc_x(&self.crafting), self.calc_y(&self.crafting))
     }
+    /// Finds the equipment slot that covers the given cell, if any.
+    pub fn equipment_slot_at(&self, pos: &Pos) -> Option<EquipmentSlot> {
+        self.equipment
+            .iter()
+            .find(|(_, coords)| coords.covers(pos))
+            .map(|(slot, _)| *slot)
+    }
+    /// Grows the inventory by the given number of columns and rows, but not past the max.
+    /// Returns false if the inventory was already as big as it gets.
+    pub fn grow_inventory(&mut self, by: Dimens, max: Dimens) -> bool {
+        let grown = Dimens((self.inventory.dimens + by).0.min(max.0));
+        if grown == self.inventory.dimens {
+            return false;
+        }
+        self.inventory.dimens = grown;
+        true
+    }
 }
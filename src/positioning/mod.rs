@@ -1,9 +1,11 @@
+pub use self::cell_mask::*;
 pub use self::coordinates::*;
 pub use self::depth::*;
 pub use self::dimensions::*;
 pub use self::grid::*;
 pub use self::position::*;
 
+mod cell_mask;
 mod coordinates;
 mod depth;
 mod dimensions;
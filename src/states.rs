@@ -3,6 +3,7 @@ use bevy::prelude::*;
 use iyes_loopless::prelude::CurrentState;
 use iyes_loopless::state::NextState;
 
+use crate::config::config_input::{InputAction, InputConfig};
 use crate::main_menu::MenuBackpack;
 use crate::transition_state::MenuTransition;
 
@@ -41,9 +42,12 @@ pub fn handle_escape(
     mut query: Query<&mut MenuBackpack>,
     mut exit: EventWriter<AppExit>,
     state: Res<CurrentState<AppState>>,
+    config: Res<InputConfig>,
     mut keys: ResMut<Input<KeyCode>>,
+    mut buttons: ResMut<Input<MouseButton>>,
+    mut pads: ResMut<Input<GamepadButton>>,
 ) {
-    if keys.clear_just_pressed(KeyCode::Escape) {
+    if config.clear_just_pressed(InputAction::Back, &mut keys, &mut buttons, &mut pads) {
         match state.0 {
             AppState::MainMenu => {
                 exit.send(AppExit);
@@ -1,11 +1,24 @@
 use bevy::prelude::*;
 use bevy::window::WindowMode;
 
+use crate::config::config_input::{InputAction, InputConfig};
+
 /// Handle some general behaviour related to the window that should be executed in any State.
-pub fn handle_window(mut keys: ResMut<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+pub fn handle_window(
+    config: Res<InputConfig>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut buttons: ResMut<Input<MouseButton>>,
+    mut pads: ResMut<Input<GamepadButton>>,
+    mut windows: ResMut<Windows>,
+) {
     let primary = windows.primary_mut();
     // Toggle fullscreen:
-    if keys.clear_just_pressed(KeyCode::F11) {
+    if config.clear_just_pressed(
+        InputAction::ToggleFullscreen,
+        &mut keys,
+        &mut buttons,
+        &mut pads,
+    ) {
         primary.set_mode(if primary.mode() != WindowMode::Windowed {
             WindowMode::Windowed
         } else {